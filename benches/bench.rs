@@ -8,7 +8,13 @@ use self::test::Bencher;
 #[bench]
 fn counting_game(b: &mut Bencher) {
     b.iter(|| {
-        let mut mcts = MCTSManager::new(CountingGame(0), MyMCTS, UCTPolicy::new(1e6), MyEvaluator);
-        mcts.playout_n(100000);
+        let mut mcts = MCTSManager::new(
+            CountingGame(0),
+            MyMCTS,
+            MyEvaluator,
+            UCTPolicy::new(1e6),
+            ApproxTable::new(),
+        );
+        mcts.playout_n(100000).unwrap();
     });
 }
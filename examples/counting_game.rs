@@ -4,10 +4,10 @@ use mcts::transposition_table::*;
 use mcts::tree_policy::*;
 use mcts::*;
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 struct CountingGame(i64);
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 enum Move {
     Add,
     Sub,
@@ -18,9 +18,7 @@ impl GameState for CountingGame {
     type Player = ();
     type MoveList = Vec<Self::Move>;
 
-    fn current_player(&self) -> Self::Player {
-        ()
-    }
+    fn current_player(&self) -> Self::Player {}
 
     fn available_moves(&self) -> Vec<Self::Move> {
         let x = self.0;
@@ -39,12 +37,6 @@ impl GameState for CountingGame {
     }
 }
 
-impl TranspositionHash for CountingGame {
-    fn hash(&self) -> u64 {
-        self.0 as u64
-    }
-}
-
 struct MyEvaluator;
 
 impl Evaluator<MyMCTS> for MyEvaluator {
@@ -73,7 +65,7 @@ impl Evaluator<MyMCTS> for MyEvaluator {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Debug)]
 struct MyMCTS;
 
 impl MCTS for MyMCTS {
@@ -87,6 +79,10 @@ impl MCTS for MyMCTS {
     fn virtual_loss(&self) -> i64 {
         500
     }
+
+    fn cycle_behaviour(&self) -> CycleBehaviour<Self> {
+        CycleBehaviour::UseCurrentEvalWhenCycleDetected
+    }
 }
 
 fn main() {
@@ -96,9 +92,9 @@ fn main() {
         MyMCTS,
         MyEvaluator,
         UCTPolicy::new(5.0),
-        ApproxTable::new(1024),
+        ApproxTable::new(),
     );
-    mcts.playout_n(100000);
+    mcts.playout_n(100000).unwrap();
     let pv: Vec<_> = mcts
         .principal_variation_states(10)
         .into_iter()
@@ -0,0 +1,432 @@
+//! Plays matches between two engines to check whether a change makes the search
+//! stronger.
+//!
+//! The engines may use different specs, evaluators, tree policies and budgets, as
+//! long as they play the same game. Colours alternate between games, and the report
+//! gives the score of the first engine with an Elo estimate. An optional SPRT stops
+//! the match as soon as the result is clear.
+//!
+//! Only two-player games are supported. The player to move first in each game is
+//! told apart from the other one with `PartialEq`.
+
+use super::*;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// The result of a game for one player.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+}
+
+impl Outcome {
+    fn reversed(self) -> Self {
+        match self {
+            Outcome::Win => Outcome::Loss,
+            Outcome::Draw => Outcome::Draw,
+            Outcome::Loss => Outcome::Win,
+        }
+    }
+}
+
+/// A manager together with the budget it searches with before each move.
+pub struct Engine<Spec: MCTS> {
+    // `None` only while the manager is being reset
+    manager: Option<MCTSManager<Spec>>,
+    limits: SearchLimits,
+    num_threads: usize,
+    selection: MoveSelection,
+}
+
+impl<Spec: MCTS + 'static> Engine<Spec>
+where
+    ThreadData<Spec>: Default,
+{
+    /// An engine that searches with one thread and plays the most visited move.
+    pub fn new(manager: MCTSManager<Spec>, limits: SearchLimits) -> Self {
+        Self {
+            manager: Some(manager),
+            limits,
+            num_threads: 1,
+            selection: MoveSelection::MaxVisits,
+        }
+    }
+
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = num_threads;
+        self
+    }
+
+    pub fn move_selection(mut self, selection: MoveSelection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn manager(&self) -> &MCTSManager<Spec> {
+        self.manager.as_ref().unwrap()
+    }
+
+    pub fn into_manager(mut self) -> MCTSManager<Spec> {
+        self.manager.take().unwrap()
+    }
+
+    fn manager_mut(&mut self) -> &mut MCTSManager<Spec> {
+        self.manager.as_mut().unwrap()
+    }
+
+    fn reset(&mut self, state: Spec::State) {
+        let manager = self.manager.take().unwrap();
+        self.manager = Some(manager.reset(state));
+    }
+
+    fn choose_move(&mut self) -> Result<Move<Spec>, SearchError> {
+        let (limits, num_threads, selection) = (self.limits, self.num_threads, self.selection);
+        let manager = self.manager_mut();
+        manager.search(limits, num_threads)?;
+        Ok(manager
+            .best_move(selection)
+            .expect("the search didn't visit any move"))
+    }
+}
+
+/// A sequential probability ratio test of the hypotheses that the first engine is
+/// `elo0` stronger than the second (H0) or `elo1` stronger (H1), with false positive
+/// rate `alpha` and false negative rate `beta`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64, alpha: f64, beta: f64) -> Self {
+        assert!(
+            elo0 < elo1,
+            "elo0 ({}) must be less than elo1 ({})",
+            elo0,
+            elo1
+        );
+        assert!(alpha > 0.0 && alpha < 1.0, "alpha is {}", alpha);
+        assert!(beta > 0.0 && beta < 1.0, "beta is {}", beta);
+        Self {
+            elo0,
+            elo1,
+            alpha,
+            beta,
+        }
+    }
+
+    pub fn lower_bound(&self) -> f64 {
+        (self.beta / (1.0 - self.alpha)).ln()
+    }
+
+    pub fn upper_bound(&self) -> f64 {
+        ((1.0 - self.beta) / self.alpha).ln()
+    }
+
+    /// The log-likelihood ratio of H1 against H0 after `report`, using the normal
+    /// approximation to the trinomial distribution of game results.
+    pub fn llr(&self, report: &ArenaReport) -> f64 {
+        let games = report.games() as f64;
+        if games == 0.0 {
+            return 0.0;
+        }
+        // Half a win and half a loss are added to the counts, so that the variance
+        // isn't zero when every game so far had the same result.
+        let variance = score_variance(
+            report.wins as f64 + 0.5,
+            report.draws as f64,
+            report.losses as f64 + 0.5,
+        );
+        let score0 = expected_score(self.elo0);
+        let score1 = expected_score(self.elo1);
+        let score = report.score();
+        games * (score1 - score0) * (2.0 * score - score0 - score1) / (2.0 * variance)
+    }
+
+    pub fn decision(&self, report: &ArenaReport) -> Option<SprtDecision> {
+        let llr = self.llr(report);
+        if llr >= self.upper_bound() {
+            Some(SprtDecision::AcceptH1)
+        } else if llr <= self.lower_bound() {
+            Some(SprtDecision::AcceptH0)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SprtDecision {
+    AcceptH0,
+    AcceptH1,
+}
+
+/// The results of a match, from the point of view of the first engine.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ArenaReport {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+    /// Set if the match was stopped by its SPRT.
+    pub sprt_decision: Option<SprtDecision>,
+}
+
+impl ArenaReport {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+
+    /// The mean score per game, counting a draw as half a win.
+    pub fn score(&self) -> f64 {
+        let games = self.games();
+        if games == 0 {
+            return 0.5;
+        }
+        (self.wins as f64 + 0.5 * self.draws as f64) / games as f64
+    }
+
+    /// The estimated Elo difference between the engines. Infinite if one of them won
+    /// every game.
+    pub fn elo(&self) -> f64 {
+        elo_from_score(self.score())
+    }
+
+    /// Half the width of the 95% confidence interval of `elo`, from the standard error
+    /// of the score scaled by the slope of the Elo curve. Infinite if `elo` is.
+    pub fn elo_error(&self) -> f64 {
+        let games = self.games();
+        let score = self.score();
+        if games == 0 || score <= 0.0 || score >= 1.0 {
+            return f64::INFINITY;
+        }
+        let variance = score_variance(self.wins as f64, self.draws as f64, self.losses as f64);
+        let deviation = 1.96 * (variance / games as f64).sqrt();
+        let slope = 400.0 / (std::f64::consts::LN_10 * score * (1.0 - score));
+        deviation * slope
+    }
+
+    fn add(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Win => self.wins += 1,
+            Outcome::Draw => self.draws += 1,
+            Outcome::Loss => self.losses += 1,
+        }
+    }
+}
+
+impl Display for ArenaReport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "W/D/L {}/{}/{}, Elo {:.1} +/- {:.1}",
+            self.wins,
+            self.draws,
+            self.losses,
+            self.elo(),
+            self.elo_error()
+        )?;
+        if let Some(decision) = self.sprt_decision {
+            write!(f, ", SPRT {:?}", decision)?;
+        }
+        Ok(())
+    }
+}
+
+/// The variance of the score of one game, estimated from the number of each result.
+fn score_variance(wins: f64, draws: f64, losses: f64) -> f64 {
+    let games = wins + draws + losses;
+    let score = (wins + 0.5 * draws) / games;
+    let deviation = |x: f64, n: f64| n * (x - score) * (x - score);
+    (deviation(1.0, wins) + deviation(0.5, draws) + deviation(0.0, losses)) / games
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Plays games between two engines. `new_game` gives the starting position of each
+/// game, and `outcome` the result of a finished game for the given player.
+pub struct Arena<A: MCTS, B: MCTS, F, G> {
+    first: Engine<A>,
+    second: Engine<B>,
+    new_game: F,
+    outcome: G,
+    max_moves: Option<usize>,
+    sprt: Option<Sprt>,
+}
+
+impl<A, B, F, G> Arena<A, B, F, G>
+where
+    A: MCTS + 'static,
+    B: MCTS<State = A::State> + 'static,
+    ThreadData<A>: Default,
+    ThreadData<B>: Default,
+    Player<A>: PartialEq,
+    F: FnMut() -> A::State,
+    G: FnMut(&A::State, &Player<A>) -> Outcome,
+{
+    pub fn new(first: Engine<A>, second: Engine<B>, new_game: F, outcome: G) -> Self {
+        Self {
+            first,
+            second,
+            new_game,
+            outcome,
+            max_moves: None,
+            sprt: None,
+        }
+    }
+
+    /// Games that reach this many moves are scored as draws.
+    pub fn max_moves(mut self, max_moves: usize) -> Self {
+        self.max_moves = Some(max_moves);
+        self
+    }
+
+    /// Stops the match early once `sprt` accepts one of its hypotheses.
+    pub fn sprt(mut self, sprt: Sprt) -> Self {
+        self.sprt = Some(sprt);
+        self
+    }
+
+    /// Plays up to `num_games` games. The first engine moves first in the even games.
+    pub fn play(&mut self, num_games: usize) -> Result<ArenaReport, SearchError> {
+        let mut report = ArenaReport::default();
+        for game in 0..num_games {
+            let outcome = self.play_game(game % 2 == 0)?;
+            report.add(outcome);
+            if let Some(sprt) = self.sprt {
+                report.sprt_decision = sprt.decision(&report);
+                if report.sprt_decision.is_some() {
+                    break;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// Plays one game and returns the outcome for the first engine.
+    fn play_game(&mut self, first_moves_first: bool) -> Result<Outcome, SearchError> {
+        let mut state = (self.new_game)();
+        self.first.reset(state.clone());
+        self.second.reset(state.clone());
+        let first_player = state.current_player();
+
+        let mut num_moves = 0;
+        while state.available_moves().into_iter().next().is_some() {
+            if self.max_moves.is_some_and(|x| num_moves >= x) {
+                return Ok(Outcome::Draw);
+            }
+            let first_to_move = (state.current_player() == first_player) == first_moves_first;
+            let mov = if first_to_move {
+                self.first.choose_move()?
+            } else {
+                self.second.choose_move()?
+            };
+            self.first.manager_mut().advance(&mov);
+            self.second.manager_mut().advance(&mov);
+            state.make_move(&mov);
+            num_moves += 1;
+        }
+
+        let outcome = (self.outcome)(&state, &first_player);
+        Ok(if first_moves_first {
+            outcome
+        } else {
+            outcome.reversed()
+        })
+    }
+
+    pub fn into_engines(self) -> (Engine<A>, Engine<B>) {
+        (self.first, self.second)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(wins: u32, draws: u32, losses: u32) -> ArenaReport {
+        ArenaReport {
+            wins,
+            draws,
+            losses,
+            sprt_decision: None,
+        }
+    }
+
+    fn sprt() -> Sprt {
+        Sprt::new(0.0, 5.0, 0.05, 0.05)
+    }
+
+    #[test]
+    fn sprt_decides_one_sided_matches() {
+        let sprt = sprt();
+        assert_eq!(sprt.llr(&report(0, 0, 0)), 0.0);
+        assert_eq!(sprt.decision(&report(0, 0, 0)), None);
+
+        assert!(sprt.llr(&report(200, 0, 0)).is_finite());
+        assert_eq!(
+            sprt.decision(&report(200, 0, 0)),
+            Some(SprtDecision::AcceptH1)
+        );
+        assert_eq!(
+            sprt.decision(&report(0, 0, 200)),
+            Some(SprtDecision::AcceptH0)
+        );
+        // no gain at all is evidence for H0
+        assert_eq!(
+            sprt.decision(&report(0, 200, 0)),
+            Some(SprtDecision::AcceptH0)
+        );
+        // a handful of games isn't enough
+        assert_eq!(sprt.decision(&report(5, 0, 0)), None);
+        assert_eq!(sprt.decision(&report(10, 0, 10)), None);
+    }
+
+    #[test]
+    fn llr_follows_the_score() {
+        let sprt = sprt();
+        let llr = sprt.llr(&report(60, 0, 40));
+        assert!(llr > 0.0);
+        assert!(llr < sprt.llr(&report(120, 0, 80)));
+        assert!(sprt.llr(&report(40, 0, 60)) < 0.0);
+        assert!(sprt.lower_bound() < 0.0 && sprt.upper_bound() > 0.0);
+    }
+
+    #[test]
+    fn elo_of_known_results() {
+        assert_eq!(report(0, 0, 0).elo(), 0.0);
+        assert_eq!(report(10, 0, 10).elo(), 0.0);
+        assert_eq!(report(0, 20, 0).elo(), 0.0);
+        assert!((report(75, 0, 25).elo() - 400.0 * 3f64.log10()).abs() < 1e-9);
+        assert!((report(25, 0, 75).elo() + 400.0 * 3f64.log10()).abs() < 1e-9);
+        assert_eq!(report(20, 0, 0).elo(), f64::INFINITY);
+        assert_eq!(report(0, 0, 20).elo(), f64::NEG_INFINITY);
+    }
+
+    #[test]
+    fn elo_error_of_known_results() {
+        // a standard deviation of 0.05 around a score of 0.5
+        let error = report(50, 0, 50).elo_error();
+        let expected = 1.96 * 0.05 * 1600.0 / std::f64::consts::LN_10;
+        assert!((error - expected).abs() < 1e-9, "{}", error);
+        assert!(report(200, 0, 200).elo_error() < error);
+
+        let error = report(19, 0, 1).elo_error();
+        assert!(error.is_finite() && error > 0.0, "{}", error);
+        assert!(!report(19, 0, 1).to_string().contains("NaN"));
+
+        assert_eq!(report(0, 0, 0).elo_error(), f64::INFINITY);
+        assert_eq!(report(20, 0, 0).elo_error(), f64::INFINITY);
+        assert_eq!(report(0, 10, 0).elo_error(), 0.0);
+    }
+}
@@ -15,6 +15,6 @@ pub type FakeI64 = i64;
 
 pub type AtomicPtr<T> = std::sync::atomic::AtomicPtr<T>;
 pub type AtomicBool = std::sync::atomic::AtomicBool;
-pub type AtomicIsize = std::sync::atomic::AtomicIsize;
 pub type AtomicUsize = std::sync::atomic::AtomicUsize;
+pub type AtomicU64 = std::sync::atomic::AtomicU64;
 pub use std::sync::atomic::Ordering;
@@ -13,7 +13,7 @@
 //! //
 //! // The best strategy is to increase the number at every step.
 //!
-//! #[derive(Clone, Debug, PartialEq)]
+//! #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 //! struct CountingGame(i64);
 //!
 //! #[derive(Clone, Debug, PartialEq)]
@@ -45,12 +45,6 @@
 //!     }
 //! }
 //!
-//! impl TranspositionHash for CountingGame {
-//!     fn hash(&self) -> u64 {
-//!         self.0 as u64
-//!     }
-//! }
-//!
 //! struct MyEvaluator;
 //!
 //! impl Evaluator<MyMCTS> for MyEvaluator {
@@ -69,7 +63,7 @@
 //!     }
 //! }
 //!
-//! #[derive(Default)]
+//! #[derive(Default, Debug)]
 //! struct MyMCTS;
 //!
 //! impl MCTS for MyMCTS {
@@ -87,10 +81,10 @@
 //!
 //! let game = CountingGame(0);
 //! let mut mcts = MCTSManager::new(game, MyMCTS, MyEvaluator, UCTPolicy::new(0.5),
-//!     ApproxTable::new(1024));
-//! mcts.playout_n_parallel(10000, 4); // 10000 playouts, 4 search threads
+//!     ApproxTable::new());
+//! mcts.playout_n_parallel(10000, 4).unwrap(); // 10000 playouts, 4 search threads
 //! mcts.tree().debug_moves();
-//! assert_eq!(mcts.best_move().unwrap(), Move::Add);
+//! assert_eq!(mcts.best_move(MoveSelection::MaxVisits).unwrap(), Move::Add);
 //! assert_eq!(mcts.principal_variation(50),
 //!     vec![Move::Add; 50]);
 //! assert_eq!(mcts.principal_variation_states(5),
@@ -107,14 +101,28 @@ extern crate crossbeam;
 extern crate rand;
 extern crate smallvec;
 
+pub mod arena;
 mod atomics;
+mod move_selection;
+mod sampling;
+mod search_info;
+mod search_limits;
 mod search_tree;
+pub mod selfplay;
+#[cfg(test)]
+mod test_game;
+mod thread_pool;
 pub mod transposition_table;
 pub mod tree_policy;
 
+pub use move_selection::*;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+pub use sampling::DirichletNoise;
+use search_info::InfoReporter;
+pub use search_info::{InfoInterval, MoveAnalysis, PerfSample, SearchInfo};
+pub use search_limits::*;
 pub use search_tree::*;
+use thread_pool::ThreadPool;
 use transposition_table::*;
 use tree_policy::*;
 
@@ -122,7 +130,9 @@ use atomics::*;
 use std::fmt::Debug;
 use std::mem;
 use std::sync::Arc;
+use std::thread;
 use std::thread::JoinHandle;
+use std::time::Duration;
 
 pub trait MCTS: Sized + Sync + Debug {
     type State: GameState + Sync + Send + Debug + PartialEq;
@@ -141,10 +151,11 @@ pub trait MCTS: Sized + Sync + Debug {
     }
 
     fn node_limit(&self) -> usize {
-        std::usize::MAX
+        usize::MAX
     }
 
-    /// `playout` panics when this length is exceeded. Defaults to one million.
+    /// A playout that reaches this length fails with `SearchError::PlayoutTooLong`.
+    /// Defaults to one million.
     fn max_playout_length(&self) -> usize {
         1_000_000
     }
@@ -221,11 +232,37 @@ pub trait Evaluator<Spec: MCTS>: Sync {
 }
 
 pub struct MCTSManager<Spec: MCTS> {
-    state: Spec::State,
-    search_tree: SearchTree<Spec>,
+    // boxed so that background workers keep a valid reference if the manager is moved
+    search_tree: Box<SearchTree<Spec>>,
     // thread local data when we have no asynchronous workers
     single_threaded_tld: Option<ThreadData<Spec>>,
     print_on_playout_error: bool,
+    // moves committed since the search began, for `TemperatureSchedule`
+    move_number: usize,
+    root_noise: Option<DirichletNoise>,
+    seed: Option<u64>,
+    // the number of seeds handed out so far, so every thread gets a different one
+    seeds_used: u64,
+    // used to sample moves, e.g. by `move_best_random_n`
+    rng: PolicyRng,
+    // workers for multi-threaded searches, kept alive between searches
+    thread_pool: ThreadPool<Spec>,
+    // threads started by `playout_parallel_async`. These are owned by the manager
+    // rather than by `AsyncSearch`, so that forgetting an `AsyncSearch` can't leave
+    // them running after the search tree is dropped.
+    background_workers: BackgroundWorkers,
+}
+
+impl<Spec: MCTS> MCTSManager<Spec> {
+    fn halt_background_workers(&mut self) {
+        self.background_workers.halt();
+    }
+}
+
+impl<Spec: MCTS> Drop for MCTSManager<Spec> {
+    fn drop(&mut self) {
+        self.halt_background_workers();
+    }
 }
 
 impl<Spec: MCTS + 'static> MCTSManager<Spec>
@@ -239,13 +276,19 @@ where
         tree_policy: Spec::TreePolicy,
         table: Spec::TranspositionTable,
     ) -> Self {
-        let search_tree = SearchTree::new(state.clone(), manager, tree_policy, eval, table);
+        let search_tree = Box::new(SearchTree::new(state, manager, tree_policy, eval, table));
         let single_threaded_tld = None;
         Self {
-            state,
             search_tree,
             single_threaded_tld,
             print_on_playout_error: true,
+            move_number: 0,
+            root_noise: None,
+            seed: None,
+            seeds_used: 0,
+            rng: PolicyRng::new(),
+            thread_pool: ThreadPool::new(),
+            background_workers: BackgroundWorkers::new(),
         }
     }
 
@@ -254,280 +297,527 @@ where
         self
     }
 
-    pub fn playout(&mut self) {
-        // Avoid overhead of thread creation
-        if self.single_threaded_tld.is_none() {
-            self.single_threaded_tld = Some(Default::default());
+    /// Makes the search reproducible. Every search thread and every random move choice
+    /// gets its own seed derived from `seed`, so a single-threaded search from the same
+    /// state with the same seed builds exactly the same tree. Multi-threaded searches
+    /// still depend on how the threads are scheduled.
+    pub fn seed(&mut self, seed: u64) -> &mut Self {
+        self.seed = Some(seed);
+        self.reseed();
+        self
+    }
+
+    /// Mixes Dirichlet noise into the root's move evaluations now, and again every time
+    /// the root changes, to make self-play explore. The noise is drawn from the seeded
+    /// random number generator, and mixed in by `TreePolicy::noisy_evaluation`:
+    /// `AlphaGoPolicy` uses `(1 - epsilon) * prior + epsilon * noise`, so the priors
+    /// still sum to one. Only the current root is noisy; the clean evaluations are put
+    /// back when the root changes, and `None` removes the noise.
+    pub fn root_noise(&mut self, noise: Option<DirichletNoise>) -> &mut Self {
+        self.root_noise = noise;
+        if noise.is_none() {
+            self.halt_background_workers();
+            self.search_tree.remove_root_noise();
         }
-        self.search_tree.playout(
-            self.state.clone(),
-            self.single_threaded_tld.as_mut().unwrap(),
-        );
+        self.add_root_noise();
+        self
     }
 
-    pub fn playout_until<Predicate: FnMut() -> bool>(&mut self, mut pred: Predicate) {
-        while !pred() {
-            self.playout();
+    fn add_root_noise(&mut self) {
+        let noise = match self.root_noise {
+            Some(noise) => noise,
+            None => return,
+        };
+        let num_moves = self.search_tree.root_search_node().moves.len();
+        if num_moves == 0 {
+            return;
         }
+        self.halt_background_workers();
+        let weights = noise.sample(num_moves, &mut self.rng);
+        self.search_tree.add_root_noise(&weights, noise.epsilon());
     }
 
-    pub fn playout_n(&mut self, n: u64) {
-        for _ in 0..n {
-            self.playout();
-        }
-    }
-
-    // unsafe fn spawn_worker_thread(&self, stop_signal: Arc<AtomicBool>) -> JoinHandle<()> {
-    //     // ignore the lifetime
-    //     let search_tree = mem::transmute::<_, &SearchTree<Spec>>(&self.search_tree);
-    //     let print_on_playout_error = self.print_on_playout_error;
-
-    //     thread::spawn(move || {
-    //         let mut tld = Default::default();
-    //         loop {
-    //             if stop_signal.load(Ordering::SeqCst) {
-    //                 break;
-    //             }
-    //             if !search_tree.playout(&mut tld) {
-    //                 if print_on_playout_error {
-    //                     eprintln!(
-    //                         "Node limit of {} reached. Halting search.",
-    //                         search_tree.spec().node_limit()
-    //                     );
-    //                 }
-    //                 break;
-    //             }
-    //         }
-    //     })
-    // }
-
-    // pub fn playout_parallel_async<'a>(&'a mut self, num_threads: usize) -> AsyncSearch<'a, Spec> {
-    //     assert!(num_threads != 0);
-    //     let stop_signal = Arc::new(AtomicBool::new(false));
-    //     let threads = (0..num_threads)
-    //         .map(|_| {
-    //             let stop_signal = stop_signal.clone();
-    //             unsafe { self.spawn_worker_thread(stop_signal) }
-    //         })
-    //         .collect();
-    //     AsyncSearch {
-    //         manager: self,
-    //         stop_signal,
-    //         threads,
-    //     }
-    // }
-
-    // pub fn into_playout_parallel_async(self, num_threads: usize) -> AsyncSearchOwned<Spec> {
-    //     assert!(num_threads != 0);
-    //     let self_box = Box::new(self);
-    //     let stop_signal = Arc::new(AtomicBool::new(false));
-    //     let threads = (0..num_threads)
-    //         .map(|_| {
-    //             let stop_signal = stop_signal.clone();
-    //             unsafe { self_box.spawn_worker_thread(stop_signal) }
-    //         })
-    //         .collect();
-
-    //     AsyncSearchOwned {
-    //         manager: Some(self_box),
-    //         stop_signal,
-    //         threads,
-    //     }
-    // }
-
-    // pub fn playout_parallel_for(&mut self, duration: Duration, num_threads: usize) {
-    //     let search = self.playout_parallel_async(num_threads);
-    //     std::thread::sleep(duration);
-    //     search.halt();
-    // }
-
-    pub fn best_moves(&self) -> Vec<&MoveInfo<Spec>> {
-        let node = self.get_search_node().unwrap();
-
-        let mut moves = node.moves.iter().collect::<Vec<_>>();
-        moves.sort_by_key(|info| -info.sum_rewards() / info.visits() as i64);
-        moves
+    fn reseed(&mut self) {
+        self.seeds_used = 0;
+        self.single_threaded_tld = None;
+        self.thread_pool = ThreadPool::new();
+        self.rng = match self.next_seed() {
+            Some(seed) => PolicyRng::from_seed(seed),
+            None => PolicyRng::new(),
+        };
     }
 
-    pub fn move_custom(&mut self, mov: Move<Spec>) {
+    fn next_seed(&mut self) -> Option<u64> {
+        let seed = self.seed?;
+        self.seeds_used += 1;
+        Some(derive_seed(seed, self.seeds_used))
+    }
+
+    fn grow_thread_pool(&mut self, num_threads: usize) {
+        while self.thread_pool.len() < num_threads {
+            let seed = self.next_seed();
+            self.thread_pool.add_worker(seed);
+        }
+    }
+
+    fn init_single_threaded_tld(&mut self) {
         if self.single_threaded_tld.is_none() {
-            self.single_threaded_tld = Some(Default::default());
+            let seed = self.next_seed();
+            self.single_threaded_tld = Some(new_thread_data(&self.search_tree, seed));
         }
+    }
 
-        let parent_search_node = unsafe {
-            mem::transmute::<&SearchNode<Spec>, &SearchNode<Spec>>(self.get_search_node().unwrap())
-        };
+    pub fn playout(&mut self) -> Result<(), SearchError> {
+        // Avoid overhead of thread creation
+        self.init_single_threaded_tld();
+        let result = self
+            .search_tree
+            .playout(self.single_threaded_tld.as_mut().unwrap());
+        if let Err(e) = result {
+            self.on_playout_error(e);
+        }
+        result
+    }
 
-        self.state.make_move(&mov);
+    pub fn playout_until<Predicate: FnMut() -> bool>(
+        &mut self,
+        mut pred: Predicate,
+    ) -> Result<(), SearchError> {
+        while !pred() {
+            self.playout()?;
+        }
+        Ok(())
+    }
 
-        let move_info = parent_search_node
-            .moves
-            .iter()
-            .find(|info| *info.get_move() == mov)
-            .unwrap();
+    /// Runs `n` playouts, stopping at the first one that fails.
+    pub fn playout_n(&mut self, n: u64) -> Result<(), SearchError> {
+        for _ in 0..n {
+            self.playout()?;
+        }
+        Ok(())
+    }
 
-        if self.get_search_node().is_none() {
-            // println!("create node by descending");
+    fn on_playout_error(&self, e: SearchError) {
+        if self.print_on_playout_error {
+            eprintln!("Halting search: {}", e);
+        }
+    }
 
-            match move_info.child() {
-                Some(child) => {
-                    // println!("insert already existed");
-                    self.search_tree.table.insert(&self.state, unsafe {
-                        &*(child.into_raw() as *const SearchNode<Spec>)
-                    });
+    /// # Safety
+    ///
+    /// The returned thread holds a reference to the search tree, so it must be joined
+    /// before the tree is dropped or replaced.
+    unsafe fn spawn_worker_thread(
+        &self,
+        stop_signal: Arc<AtomicBool>,
+        seed: Option<u64>,
+    ) -> JoinHandle<()> {
+        // ignore the lifetime
+        let search_tree =
+            mem::transmute::<&SearchTree<Spec>, &'static SearchTree<Spec>>(&self.search_tree);
+        let print_on_playout_error = self.print_on_playout_error;
+
+        thread::spawn(move || {
+            let mut tld = new_thread_data(search_tree, seed);
+            loop {
+                if stop_signal.load(Ordering::SeqCst) {
+                    break;
                 }
-                None => {
-                    // println!("child ptr is really none");
-                    let _ = self.search_tree.descend(
-                        &self.state,
-                        move_info,
-                        parent_search_node,
-                        &mut self.single_threaded_tld.as_mut().unwrap(),
-                    );
+                if let Err(e) = search_tree.playout(&mut tld) {
+                    if print_on_playout_error {
+                        eprintln!("Halting search: {}", e);
+                    }
+                    break;
                 }
             }
+        })
+    }
+
+    fn spawn_background_workers(&mut self, num_threads: usize) {
+        assert!(num_threads != 0);
+        self.halt_background_workers();
+        for _ in 0..num_threads {
+            let stop_signal = self.background_workers.stop_signal.clone();
+            let seed = self.next_seed();
+            // Each thread is recorded as soon as it exists, so `halt_background_workers`
+            // (which runs on drop) always joins every thread that can see the tree.
+            let thread = unsafe { self.spawn_worker_thread(stop_signal, seed) };
+            self.background_workers.threads.push(thread);
         }
     }
 
-    pub fn move_best_random_n(&mut self, n: usize) -> Move<Spec> {
-        if self.single_threaded_tld.is_none() {
-            self.single_threaded_tld = Some(Default::default());
+    /// Starts `num_threads` threads searching in the background. The search stops when
+    /// the returned `AsyncSearch` is halted or dropped.
+    pub fn playout_parallel_async(&mut self, num_threads: usize) -> AsyncSearch<'_, Spec> {
+        self.spawn_background_workers(num_threads);
+        AsyncSearch { manager: self }
+    }
+
+    /// Like `playout_parallel_async`, but the search takes ownership of the manager and
+    /// hands it back from `AsyncSearchOwned::halt`.
+    pub fn into_playout_parallel_async(mut self, num_threads: usize) -> AsyncSearchOwned<Spec> {
+        self.spawn_background_workers(num_threads);
+        AsyncSearchOwned {
+            manager: Some(self),
         }
+    }
 
-        let parent_search_node = unsafe {
-            mem::transmute::<&SearchNode<Spec>, &SearchNode<Spec>>(self.get_search_node().unwrap())
-        };
+    /// The root moves, ordered from best to worst by `selection`. Only the moves
+    /// allowed by `restrict_root_moves` are included.
+    pub fn best_moves(&self, selection: MoveSelection) -> Vec<&MoveInfo<Spec>> {
+        selection.rank(self.search_tree.root_moves())
+    }
 
-        let mut rng = thread_rng();
-        let optimal_move_info = unsafe {
-            let best_moves = self.best_moves();
+    /// The best root move according to `selection`, or `None` if no move has been
+    /// visited.
+    pub fn best_move(&self, selection: MoveSelection) -> Option<Move<Spec>> {
+        self.best_moves(selection)
+            .into_iter()
+            .next()
+            .filter(|x| x.visits() > 0)
+            .map(|x| x.get_move().clone())
+    }
 
-            let infos = if best_moves.len() > 10 {
-                &best_moves[0..n]
-            } else {
-                &best_moves
-            };
+    /// The probability of playing each root move. Policies for simultaneous-move games,
+    /// such as `Exp3Policy`, report their average mixed strategy; for other policies
+    /// this is the proportion of visits.
+    pub fn root_strategy(&self) -> Vec<(&MoveInfo<Spec>, f64)> {
+        self.search_tree.root_strategy()
+    }
 
-            // println!("best moves: {:?}", infos);
+    /// Commits `mov`. The child it leads to becomes the root of the search tree, and
+    /// the statistics gathered below it are kept.
+    pub fn advance(&mut self, mov: &Move<Spec>) {
+        self.halt_background_workers();
+        self.init_single_threaded_tld();
+        self.search_tree
+            .advance(mov, self.single_threaded_tld.as_mut().unwrap());
+        self.move_number += 1;
+        self.add_root_noise();
+    }
+
+    pub fn move_custom(&mut self, mov: Move<Spec>) {
+        self.advance(&mov);
+    }
 
-            mem::transmute::<&MoveInfo<Spec>, &MoveInfo<Spec>>(infos.choose(&mut rng).unwrap())
+    /// Commits a move chosen uniformly from the `n` visited root moves with the highest
+    /// mean reward. Panics if `n` is zero or no root move has been visited.
+    pub fn move_best_random_n(&mut self, n: usize) -> Move<Spec> {
+        assert!(n > 0, "n is {} (must be positive)", n);
+        let optimal_move = {
+            let best_moves: Vec<_> = self
+                .best_moves(MoveSelection::MaxValue)
+                .into_iter()
+                .filter(|x| x.visits() > 0)
+                .map(|x| x.get_move().clone())
+                .collect();
+
+            assert!(!best_moves.is_empty(), "no root move has been visited");
+            let n = n.min(best_moves.len());
+            best_moves[..n].choose(&mut self.rng).unwrap().clone()
         };
-        let optimal_move = optimal_move_info.get_move().clone();
 
-        self.state.make_move(&optimal_move);
+        self.advance(&optimal_move);
+
+        optimal_move
+    }
 
-        if self.get_search_node().is_none() {
-            // println!("create node by descending");
+    /// Picks a root move with probability proportional to `visits^(1 / temperature)`,
+    /// without committing it. Returns `None` if no root move has been visited.
+    pub fn sample_move(&mut self, temperature: f64) -> Option<Move<Spec>> {
+        let moves: Vec<_> = self.search_tree.root_moves().collect();
+        sample_by_visits(&moves, temperature, &mut self.rng).map(|x| x.get_move().clone())
+    }
 
-            match optimal_move_info.child() {
-                Some(child) => {
-                    // println!("insert already existed");
-                    self.search_tree.table.insert(&self.state, unsafe {
-                        &*(child.into_raw() as *const SearchNode<Spec>)
-                    });
-                }
-                None => {
-                    // println!("child ptr is really none");
-                    let _ = self.search_tree.descend(
-                        &self.state,
-                        optimal_move_info,
-                        parent_search_node,
-                        &mut self.single_threaded_tld.as_mut().unwrap(),
-                    );
-                }
-            }
-        }
+    /// Samples a move at the temperature `schedule` gives for the current move number,
+    /// and commits it. Returns `None`, without committing anything, if no root move has
+    /// been visited.
+    pub fn move_by_temperature(&mut self, schedule: &TemperatureSchedule) -> Option<Move<Spec>> {
+        let mov = self.sample_move(schedule.temperature(self.move_number))?;
+        self.advance(&mov);
+        Some(mov)
+    }
 
-        optimal_move
+    /// The number of moves committed since the manager was created or reset.
+    pub fn move_number(&self) -> usize {
+        self.move_number
+    }
+
+    /// Makes the search choose only among `moves` at the root, like UCI's
+    /// `searchmoves`. The moves below the root aren't restricted. The restriction lasts
+    /// until the next move is committed or `clear_root_restriction` is called.
+    pub fn restrict_root_moves(&mut self, moves: &[Move<Spec>]) {
+        self.halt_background_workers();
+        self.search_tree.restrict_root_moves(moves);
+    }
+
+    pub fn clear_root_restriction(&mut self) {
+        self.halt_background_workers();
+        self.search_tree.clear_root_restriction();
     }
 
     pub fn get_search_node(&self) -> Option<&SearchNode<Spec>> {
-        self.search_tree.get_node(&self.state)
+        Some(self.search_tree.root_search_node())
     }
 
-    pub fn playout_n_parallel(&mut self, n: u64, num_threads: usize) {
+    pub fn playout_n_parallel(&mut self, n: u64, num_threads: usize) -> Result<(), SearchError> {
         if n == 0 {
-            return;
+            return Ok(());
         }
+        match self.search(SearchLimits::new().playouts(n), num_threads)? {
+            StopReason::Nodes => Err(SearchError::NodeLimitReached),
+            _ => Ok(()),
+        }
+    }
+
+    /// Searches with `num_threads` threads until `duration` has passed. Every thread
+    /// checks the deadline before starting a playout, so this returns at most one
+    /// playout's worth of time late.
+    pub fn playout_parallel_for(
+        &mut self,
+        duration: Duration,
+        num_threads: usize,
+    ) -> Result<(), SearchError> {
+        match self.search(SearchLimits::new().time(duration), num_threads)? {
+            StopReason::Nodes => Err(SearchError::NodeLimitReached),
+            _ => Ok(()),
+        }
+    }
+
+    /// Searches with `num_threads` threads until one of `limits` is reached, and returns
+    /// the limit that stopped the search. With one thread the search runs on the calling
+    /// thread.
+    ///
+    /// Reaching `MCTS::node_limit` stops the search with `StopReason::Nodes`. Any other
+    /// failed playout stops every thread, and the error is returned.
+    pub fn search(
+        &mut self,
+        limits: SearchLimits,
+        num_threads: usize,
+    ) -> Result<StopReason, SearchError> {
+        self.search_impl(limits, num_threads, None)
+    }
+
+    /// Like `search`, but calls `callback` with the progress of the search every
+    /// `interval`, and once more when the search stops. The callback runs on whichever
+    /// search thread notices that a report is due.
+    pub fn search_with_info<F>(
+        &mut self,
+        limits: SearchLimits,
+        num_threads: usize,
+        interval: InfoInterval,
+        mut callback: F,
+    ) -> Result<StopReason, SearchError>
+    where
+        F: FnMut(&SearchInfo<Spec>) + Send,
+    {
+        let reporter = InfoReporter::new(interval, self.search_tree.num_nodes(), &mut callback);
+        self.search_impl(limits, num_threads, Some(&reporter))
+    }
+
+    fn search_impl(
+        &mut self,
+        limits: SearchLimits,
+        num_threads: usize,
+        reporter: Option<&InfoReporter<Spec>>,
+    ) -> Result<StopReason, SearchError> {
         assert!(num_threads != 0);
-        let counter = AtomicIsize::new(n as isize);
-        let search_tree = &self.search_tree;
+        let budget = SearchBudget::new(limits);
+
+        if num_threads == 1 {
+            self.init_single_threaded_tld();
+            search_worker(
+                &self.search_tree,
+                &budget,
+                reporter,
+                self.single_threaded_tld.as_mut().unwrap(),
+            );
+        } else {
+            self.grow_thread_pool(num_threads);
+            let search_tree = &self.search_tree;
+            let budget = &budget;
+            self.thread_pool.run(num_threads, |worker| {
+                let tld = worker.thread_data(search_tree);
+                search_worker(search_tree, budget, reporter, tld);
+            });
+        }
+
+        if let Some(reporter) = reporter {
+            reporter.report(&self.search_tree, &budget);
+        }
+        if let Some(e) = budget.error() {
+            self.on_playout_error(e);
+            return Err(e);
+        }
+        Ok(budget.stop_reason().unwrap())
+    }
+
+    pub fn principal_variation_info(&self, num_moves: usize) -> Vec<MoveInfoHandle<'_, Spec>> {
+        let search_node = self.get_search_node().unwrap();
+
+        self.search_tree.principal_variation(search_node, num_moves)
+    }
+
+    pub fn principal_variation(&self, num_moves: usize) -> Vec<Move<Spec>> {
+        self.principal_variation_info(num_moves)
+            .into_iter()
+            .map(|x| x.get_move().clone())
+            .collect()
+    }
+
+    pub fn principal_variation_states(&self, num_moves: usize) -> Vec<Spec::State> {
+        let moves = self.principal_variation(num_moves);
+        let mut states = vec![self.search_tree.root_state().clone()];
+        for mov in moves {
+            let mut state = states[states.len() - 1].clone();
+            state.make_move(&mov);
+            states.push(state);
+        }
+        states
+    }
 
-        let state = self.state.clone();
+    /// The `num_lines` most visited root moves, each with its statistics and up to
+    /// `pv_length` moves of the line that follows it.
+    pub fn analysis(&self, num_lines: usize, pv_length: usize) -> Vec<MoveAnalysis<'_, Spec>> {
+        let node = self.search_tree.root_search_node();
+        MoveSelection::MaxVisits
+            .rank(self.search_tree.root_moves())
+            .into_iter()
+            .take(num_lines)
+            .map(|x| MoveAnalysis::new(&self.search_tree, node, x, pv_length))
+            .collect()
+    }
 
+    pub fn tree(&self) -> &SearchTree<Spec> {
+        &self.search_tree
+    }
+
+    /// Searches with `num_threads` threads and measures the throughput every `interval`,
+    /// for `num_samples` intervals. Stops early if the node limit is reached.
+    pub fn perf_test(
+        &mut self,
+        num_threads: usize,
+        interval: Duration,
+        num_samples: usize,
+    ) -> Vec<PerfSample> {
+        assert!(num_threads != 0);
+        self.grow_thread_pool(num_threads);
+        let budget = &SearchBudget::new(SearchLimits::new());
+        let search_tree = &self.search_tree;
+        let thread_pool = &mut self.thread_pool;
         crossbeam::scope(|scope| {
-            for _ in 0..num_threads {
-                scope.spawn(|_| {
-                    let mut tld = Default::default();
-                    loop {
-                        let count = counter.fetch_sub(1, Ordering::SeqCst);
-                        if count <= 0 {
-                            break;
-                        }
-                        search_tree.playout(state.clone(), &mut tld);
+            let sampler = scope.spawn(move |_| {
+                let mut samples = Vec::with_capacity(num_samples);
+                let mut last_time = budget.elapsed();
+                let mut last_nodes = search_tree.num_nodes();
+                let mut last_playouts = budget.playouts_completed();
+                for _ in 0..num_samples {
+                    if budget.is_stopped() {
+                        break;
                     }
-                });
-            }
+                    thread::sleep(interval);
+                    let time = budget.elapsed();
+                    let nodes = search_tree.num_nodes();
+                    let playouts = budget.playouts_completed();
+                    samples.push(PerfSample {
+                        elapsed: time,
+                        duration: time - last_time,
+                        nodes: nodes.saturating_sub(last_nodes),
+                        playouts: (playouts - last_playouts) as u64,
+                    });
+                    last_time = time;
+                    last_nodes = nodes;
+                    last_playouts = playouts;
+                }
+                budget.stop(StopReason::Time);
+                samples
+            });
+            thread_pool.run(num_threads, |worker| {
+                let tld = worker.thread_data(search_tree);
+                search_worker(search_tree, budget, None, tld);
+            });
+            sampler.join().unwrap()
         })
-        .unwrap();
+        .unwrap()
     }
 
-    // pub fn principal_variation_info(&self, num_moves: usize) -> Vec<MoveInfoHandle<Spec>> {
-    //     let search_node = self.search_tree.get_node(&self.state).unwrap();
-
-    //     search_node.principal_variation(num_moves)
-    // }
+    /// Runs `perf_test` for ten one-second intervals and prints each sample to stderr.
+    pub fn perf_test_to_stderr(&mut self, num_threads: usize) {
+        for sample in self.perf_test(num_threads, Duration::from_secs(1), 10) {
+            eprintln!("{}", sample);
+        }
+    }
 
-    // pub fn principal_variation(&self, num_moves: usize) -> Vec<Move<Spec>> {
-    //     let search_node = self.search_tree.get_node(&self.state).unwrap();
+    /// Discards the search tree and the contents of the transposition table, and starts
+    /// a new search from `init_state`. The spec, evaluator and tree policy are kept.
+    pub fn reset(mut self, init_state: Spec::State) -> Self {
+        self.halt_background_workers();
+        self.search_tree.reset(init_state);
+        self.move_number = 0;
+        if self.seed.is_some() {
+            self.reseed();
+        }
+        self.add_root_noise();
+        self
+    }
+}
 
-    //     search_node
-    //         .principal_variation(num_moves)
-    //         .into_iter()
-    //         .map(|x| x.get_move())
-    //         .map(|x| x.clone())
-    //         .collect()
-    // }
+fn new_thread_data<Spec: MCTS>(
+    search_tree: &SearchTree<Spec>,
+    seed: Option<u64>,
+) -> ThreadData<Spec>
+where
+    ThreadData<Spec>: Default,
+{
+    let mut tld = ThreadData::default();
+    if let Some(seed) = seed {
+        search_tree
+            .tree_policy()
+            .seed_thread_data(&mut tld.policy_data, seed);
+    }
+    tld
+}
 
-    // pub fn principal_variation_states(&self, num_moves: usize) -> Vec<Spec::State> {
-    //     let moves = self.principal_variation(num_moves);
-    //     let mut states = vec![self.search_tree.root_state().clone()];
-    //     for mov in moves {
-    //         let mut state = states[states.len() - 1].clone();
-    //         state.make_move(&mov);
-    //         states.push(state);
-    //     }
-    //     states
-    // }
+// splitmix64, so that nearby seeds give unrelated streams
+fn derive_seed(seed: u64, index: u64) -> u64 {
+    let mut z = seed.wrapping_add(index.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
 
-    pub fn tree(&self) -> &SearchTree<Spec> {
-        &self.search_tree
+fn search_worker<Spec: MCTS>(
+    search_tree: &SearchTree<Spec>,
+    budget: &SearchBudget,
+    reporter: Option<&InfoReporter<Spec>>,
+    tld: &mut ThreadData<Spec>,
+) {
+    let _sentinel = PanicSentinel(budget);
+    loop {
+        // checked before every playout, so that a time interval is also noticed
+        // while no playout is finishing
+        if let Some(reporter) = reporter {
+            reporter.maybe_report(search_tree, budget);
+        }
+        if !budget.start_playout(search_tree) {
+            break;
+        }
+        match search_tree.playout_with_depth(tld) {
+            Ok(depth) => budget.finish_playout(depth),
+            Err(SearchError::NodeLimitReached) => budget.stop(StopReason::Nodes),
+            Err(e) => budget.fail(e),
+        }
     }
+}
 
-    // pub fn perf_test<F>(&mut self, num_threads: usize, mut f: F)
-    // where
-    //     F: FnMut(usize),
-    // {
-    //     let search = self.playout_parallel_async(num_threads);
-    //     for _ in 0..10 {
-    //         let n1 = search.manager.search_tree.num_nodes();
-    //         std::thread::sleep(Duration::from_secs(1));
-    //         let n2 = search.manager.search_tree.num_nodes();
-    //         let diff = if n2 > n1 { n2 - n1 } else { 0 };
-    //         f(diff);
-    //     }
-    // }
-
-    // pub fn perf_test_to_stderr(&mut self, num_threads: usize) {
-    //     self.perf_test(num_threads, |x| {
-    //         eprintln!("{} nodes/sec", thousands_separate(x))
-    //     });
-    // }
-
-    pub fn reset(self, init_state: Spec::State) -> Self {
-        Self {
-            state: init_state,
-            ..self
+// Stops the other threads of a search when a worker panics, since the search may have
+// no limit that they would reach on their own.
+struct PanicSentinel<'a>(&'a SearchBudget);
+
+impl<'a> Drop for PanicSentinel<'a> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.abort();
         }
     }
 }
@@ -547,62 +837,68 @@ fn thousands_separate(x: usize) -> String {
 #[must_use]
 pub struct AsyncSearch<'a, Spec: 'a + MCTS> {
     manager: &'a mut MCTSManager<Spec>,
-    stop_signal: Arc<AtomicBool>,
-    threads: Vec<JoinHandle<()>>,
 }
 
 impl<'a, Spec: MCTS> AsyncSearch<'a, Spec> {
     pub fn halt(self) {}
     pub fn num_threads(&self) -> usize {
-        self.threads.len()
+        self.manager.background_workers.threads.len()
     }
 }
 
 impl<'a, Spec: MCTS> Drop for AsyncSearch<'a, Spec> {
     fn drop(&mut self) {
-        self.stop_signal.store(true, Ordering::SeqCst);
-        drain_join_unwrap(&mut self.threads);
+        self.manager.halt_background_workers();
     }
 }
 
 #[must_use]
 pub struct AsyncSearchOwned<Spec: MCTS> {
-    manager: Option<Box<MCTSManager<Spec>>>,
-    stop_signal: Arc<AtomicBool>,
-    threads: Vec<JoinHandle<()>>,
+    manager: Option<MCTSManager<Spec>>,
 }
 
 impl<Spec: MCTS> AsyncSearchOwned<Spec> {
-    fn stop_threads(&mut self) {
-        self.stop_signal.store(true, Ordering::SeqCst);
-        drain_join_unwrap(&mut self.threads);
-    }
-
     pub fn halt(mut self) -> MCTSManager<Spec> {
-        self.stop_threads();
-        *self.manager.take().unwrap()
+        let mut manager = self.manager.take().unwrap();
+        manager.halt_background_workers();
+        manager
     }
 
     pub fn num_threads(&self) -> usize {
-        self.threads.len()
-    }
-}
-
-impl<Spec: MCTS> Drop for AsyncSearchOwned<Spec> {
-    fn drop(&mut self) {
-        self.stop_threads();
+        self.manager
+            .as_ref()
+            .map_or(0, |x| x.background_workers.threads.len())
     }
 }
 
 impl<Spec: MCTS> From<MCTSManager<Spec>> for AsyncSearchOwned<Spec> {
     /// An `MCTSManager` is an `AsyncSearchOwned` with zero threads searching.
     fn from(m: MCTSManager<Spec>) -> Self {
+        Self { manager: Some(m) }
+    }
+}
+
+struct BackgroundWorkers {
+    stop_signal: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl BackgroundWorkers {
+    fn new() -> Self {
         Self {
-            manager: Some(Box::new(m)),
             stop_signal: Arc::new(AtomicBool::new(false)),
             threads: Vec::new(),
         }
     }
+
+    fn halt(&mut self) {
+        if self.threads.is_empty() {
+            return;
+        }
+        self.stop_signal.store(true, Ordering::SeqCst);
+        drain_join_unwrap(&mut self.threads);
+        self.stop_signal.store(false, Ordering::SeqCst);
+    }
 }
 
 fn drain_join_unwrap(threads: &mut Vec<JoinHandle<()>>) {
@@ -615,6 +911,230 @@ fn drain_join_unwrap(threads: &mut Vec<JoinHandle<()>>) {
 pub enum CycleBehaviour<Spec: MCTS> {
     Ignore,
     UseCurrentEvalWhenCycleDetected,
+    /// The playout fails with `SearchError::CycleDetected`.
     PanicWhenCycleDetected,
     UseThisEvalWhenCycleDetected(StateEvaluation<Spec>),
 }
+
+/// Why a playout failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SearchError {
+    /// The tree has `MCTS::node_limit` nodes.
+    NodeLimitReached,
+    /// The playout reached `MCTS::max_playout_length` moves.
+    PlayoutTooLong,
+    /// The playout came back to a node it had already visited, and `cycle_behaviour`
+    /// is `PanicWhenCycleDetected`.
+    CycleDetected,
+    /// The transposition table returned a node for a different state than the one the
+    /// playout reached.
+    StateMismatch,
+}
+
+impl std::fmt::Display for SearchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SearchError::NodeLimitReached => write!(f, "node limit reached"),
+            SearchError::PlayoutTooLong => write!(
+                f,
+                "playout length exceeded the maximum (maybe the transposition table is creating an infinite loop?)"
+            ),
+            SearchError::CycleDetected => write!(
+                f,
+                "cycle detected! you should do one of the following:\n- make states acyclic\n- remove transposition table\n- change cycle_behaviour()"
+            ),
+            SearchError::StateMismatch => write!(
+                f,
+                "the transposition table returned a node for a different state"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SearchError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+    use test_game::*;
+
+    fn root_visits(manager: &MCTSManager<AddMCTS>) -> u64 {
+        manager.tree().root_moves().map(|x| x.visits()).sum()
+    }
+
+    fn wait_for_visits(manager: &MCTSManager<AddMCTS>, visits: u64) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while root_visits(manager) < visits {
+            assert!(
+                Instant::now() < deadline,
+                "the background search made no progress"
+            );
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn async_search_starts_and_stops() {
+        let mut manager = manager();
+        let search = manager.playout_parallel_async(2);
+        assert_eq!(search.num_threads(), 2);
+        wait_for_visits(search.manager, 100);
+        search.halt();
+        assert_eq!(manager.background_workers.threads.len(), 0);
+
+        // nothing searches once the search is halted
+        let visits = root_visits(&manager);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(root_visits(&manager), visits);
+
+        let search = manager.into_playout_parallel_async(3);
+        assert_eq!(search.num_threads(), 3);
+        wait_for_visits(search.manager.as_ref().unwrap(), visits + 100);
+        let mut manager = search.halt();
+        let visits = root_visits(&manager);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!(root_visits(&manager), visits);
+        manager.playout_n(10).unwrap();
+        assert_eq!(root_visits(&manager), visits + 10);
+    }
+
+    #[test]
+    fn perf_test_samples_every_interval() {
+        for num_threads in [1, 2] {
+            let mut manager = manager();
+            let interval = Duration::from_millis(10);
+            let samples = manager.perf_test(num_threads, interval, 3);
+            assert_eq!(samples.len(), 3);
+            for sample in &samples {
+                assert!(sample.duration >= interval);
+            }
+            for pair in samples.windows(2) {
+                assert_eq!(pair[1].elapsed, pair[0].elapsed + pair[1].duration);
+            }
+            let playouts: u64 = samples.iter().map(|x| x.playouts).sum();
+            let nodes: usize = samples.iter().map(|x| x.nodes).sum();
+            assert!(playouts > 0);
+            // nodes may still be added between the last sample and the stop
+            assert!(nodes < manager.tree().num_nodes());
+
+            // the workers are stopped once the last sample is taken
+            let visits = root_visits(&manager);
+            thread::sleep(Duration::from_millis(20));
+            assert_eq!(root_visits(&manager), visits);
+        }
+    }
+
+    // Plays a few moves with a fresh manager, recording the visits and rewards of the
+    // root moves before each move.
+    fn seeded_game(seed: u64) -> Vec<(Vec<(u64, i64)>, Add)> {
+        let mut manager = manager();
+        manager.seed(seed);
+        let schedule = TemperatureSchedule::constant(1.0);
+        let mut game = Vec::new();
+        for i in 0..6 {
+            manager.playout_n(300).unwrap();
+            let stats = manager
+                .tree()
+                .root_moves()
+                .map(|x| (x.visits(), x.sum_rewards()))
+                .collect();
+            let mov = if i % 2 == 0 {
+                manager.move_best_random_n(2)
+            } else {
+                manager.move_by_temperature(&schedule).unwrap()
+            };
+            game.push((stats, mov));
+        }
+        game
+    }
+
+    #[test]
+    fn same_seed_same_game() {
+        let game = seeded_game(7);
+        assert_eq!(seeded_game(7), game);
+        // the moves are random, so some other seed plays differently
+        assert!((0..10).any(|seed| seeded_game(seed) != game));
+    }
+
+    #[test]
+    fn move_best_random_n_skips_unvisited_moves() {
+        for seed in 0..10 {
+            let mut manager = manager();
+            manager.seed(seed);
+            manager.restrict_root_moves(&[Add::Two]);
+            manager.playout_n(10).unwrap();
+            manager.clear_root_restriction();
+            assert_eq!(manager.move_best_random_n(2), Add::Two);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "must be positive")]
+    fn move_best_random_n_rejects_zero() {
+        let mut manager = manager();
+        manager.playout_n(10).unwrap();
+        manager.move_best_random_n(0);
+    }
+
+    fn root_priors(manager: &MCTSManager<AlphaGoMCTS>) -> Vec<f64> {
+        manager
+            .tree()
+            .root_moves()
+            .map(|x| *x.move_evaluation())
+            .collect()
+    }
+
+    #[test]
+    fn root_noise_is_an_overlay() {
+        let clean = [ONE_PRIOR, 1.0 - ONE_PRIOR];
+        let noise = DirichletNoise::new(0.3, 0.25);
+        let mut manager = alphago_manager(AlphaGoPolicy::new(1.0));
+        assert_eq!(root_priors(&manager), clean);
+
+        manager.root_noise(Some(noise));
+        let noisy = root_priors(&manager);
+        assert_ne!(noisy, clean);
+        assert!((noisy.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+
+        // drawing the noise again replaces it, so it never compounds
+        for _ in 0..20 {
+            manager.root_noise(Some(noise));
+            for (prior, clean) in root_priors(&manager).into_iter().zip(clean) {
+                assert!(prior >= 0.75 * clean - 1e-9, "{}", prior);
+            }
+        }
+        manager.root_noise(None);
+        assert_eq!(root_priors(&manager), clean);
+    }
+
+    #[test]
+    fn seed_fixes_root_noise() {
+        let noisy_priors = || {
+            let mut manager = alphago_manager(AlphaGoPolicy::new(1.0));
+            manager.seed(3);
+            manager.root_noise(Some(DirichletNoise::new(0.3, 0.25)));
+            root_priors(&manager)
+        };
+        assert_eq!(noisy_priors(), noisy_priors());
+    }
+
+    #[test]
+    fn root_noise_moves_with_the_root() {
+        let clean = [ONE_PRIOR, 1.0 - ONE_PRIOR];
+        let mut manager = alphago_manager(AlphaGoPolicy::new(1.0));
+        manager.root_noise(Some(DirichletNoise::new(0.3, 0.25)));
+        manager.playout_n(100).unwrap();
+        // the next root was expanded with clean priors, and gets noise of its own
+        let next = manager.tree().get_node(&AddGame(1)).unwrap();
+        let priors: Vec<f64> = next.moves.iter().map(|x| *x.move_evaluation()).collect();
+        assert_eq!(priors, clean);
+
+        manager.advance(&Add::One);
+        let noisy = root_priors(&manager);
+        assert_ne!(noisy, clean);
+        assert!((noisy.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        manager.root_noise(None);
+        assert_eq!(root_priors(&manager), clean);
+    }
+}
@@ -0,0 +1,243 @@
+use super::*;
+use rand::Rng;
+use std::cmp::{Ordering, Reverse};
+
+/// How `MCTSManager::best_move` and `best_moves` rank the root moves once the search is
+/// over. Moves that were never visited always come last.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MoveSelection {
+    /// The most visited move.
+    #[default]
+    MaxVisits,
+    /// The move with the highest mean reward.
+    MaxValue,
+    /// The move that has both the most visits and the highest mean reward. Moves are
+    /// ranked by the worse of their two ranks, so if no move is best by both measures,
+    /// the one that comes closest is chosen.
+    RobustMax,
+    /// The move with the highest lower confidence bound, `mean - c / sqrt(visits)`,
+    /// where `c` is the given constant.
+    SecureChild(f64),
+    /// The most visited move, with ties broken by mean reward.
+    MostVisitsValueTiebreak,
+}
+
+impl MoveSelection {
+    /// Returns `moves` ordered from best to worst.
+    pub fn rank<'a, Spec: MCTS, I>(&self, moves: I) -> Vec<&'a MoveInfo<Spec>>
+    where
+        I: IntoIterator<Item = &'a MoveInfo<Spec>>,
+    {
+        let (mut visited, unvisited): (Vec<_>, Vec<_>) =
+            moves.into_iter().partition(|x| x.visits() > 0);
+        match *self {
+            MoveSelection::MaxVisits => {
+                visited.sort_by_key(|x| Reverse(x.visits()));
+            }
+            MoveSelection::MaxValue => {
+                visited.sort_by(|a, b| cmp_f64(b.mean_reward(), a.mean_reward()));
+            }
+            MoveSelection::RobustMax => {
+                let ranks = robust_ranks(&visited);
+                let mut ranked: Vec<_> = ranks.into_iter().zip(visited).collect();
+                ranked.sort_by(|(rank_a, a), (rank_b, b)| {
+                    rank_a.cmp(rank_b).then(b.visits().cmp(&a.visits()))
+                });
+                visited = ranked.into_iter().map(|(_, x)| x).collect();
+            }
+            MoveSelection::SecureChild(c) => {
+                let bound = |x: &MoveInfo<Spec>| x.mean_reward() - c / (x.visits() as f64).sqrt();
+                visited.sort_by(|a, b| cmp_f64(bound(b), bound(a)));
+            }
+            MoveSelection::MostVisitsValueTiebreak => {
+                visited.sort_by(|a, b| {
+                    b.visits()
+                        .cmp(&a.visits())
+                        .then(cmp_f64(b.mean_reward(), a.mean_reward()))
+                });
+            }
+        }
+        visited.extend(unvisited);
+        visited
+    }
+}
+
+// the worse of each move's rank by visits and its rank by mean reward
+fn robust_ranks<Spec: MCTS>(moves: &[&MoveInfo<Spec>]) -> Vec<usize> {
+    let mut by_visits: Vec<usize> = (0..moves.len()).collect();
+    by_visits.sort_by_key(|&x| Reverse(moves[x].visits()));
+    let mut by_value: Vec<usize> = (0..moves.len()).collect();
+    by_value.sort_by(|&a, &b| cmp_f64(moves[b].mean_reward(), moves[a].mean_reward()));
+
+    let mut ranks = vec![0; moves.len()];
+    for (rank, (&a, &b)) in by_visits.iter().zip(by_value.iter()).enumerate() {
+        ranks[a] = ranks[a].max(rank);
+        ranks[b] = ranks[b].max(rank);
+    }
+    ranks
+}
+
+fn cmp_f64(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+/// The temperature used to sample the committed move, by move number. A move is chosen
+/// with probability proportional to `visits^(1 / temperature)`; at temperature zero
+/// the most visited move is played.
+///
+/// ```
+/// use mcts::TemperatureSchedule;
+///
+/// // explore for the first 10 moves, then play more greedily, and from move 30 on
+/// // always play the most visited move
+/// let schedule = TemperatureSchedule::constant(1.0)
+///     .then(10, 0.5)
+///     .cutoff(30);
+/// assert_eq!(schedule.temperature(12), 0.5);
+/// assert_eq!(schedule.temperature(30), 0.0);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TemperatureSchedule {
+    // (first move number, temperature), ordered by move number
+    steps: Vec<(usize, f64)>,
+    cutoff: Option<usize>,
+}
+
+impl TemperatureSchedule {
+    pub fn constant(temperature: f64) -> Self {
+        assert!(
+            temperature >= 0.0,
+            "temperature is {} (must not be negative)",
+            temperature
+        );
+        Self {
+            steps: vec![(0, temperature)],
+            cutoff: None,
+        }
+    }
+
+    /// Uses `temperature` from move number `from_move` on.
+    pub fn then(mut self, from_move: usize, temperature: f64) -> Self {
+        assert!(
+            temperature >= 0.0,
+            "temperature is {} (must not be negative)",
+            temperature
+        );
+        self.steps.retain(|&(x, _)| x < from_move);
+        self.steps.push((from_move, temperature));
+        self
+    }
+
+    /// Uses temperature zero from move number `cutoff` on.
+    pub fn cutoff(mut self, cutoff: usize) -> Self {
+        self.cutoff = Some(cutoff);
+        self
+    }
+
+    pub fn temperature(&self, move_number: usize) -> f64 {
+        if self.cutoff.is_some_and(|x| move_number >= x) {
+            return 0.0;
+        }
+        self.steps
+            .iter()
+            .rev()
+            .find(|&&(x, _)| x <= move_number)
+            .map_or(0.0, |&(_, t)| t)
+    }
+}
+
+/// Picks a move with probability proportional to `visits^(1 / temperature)`. Returns
+/// `None` if no move has been visited.
+pub(crate) fn sample_by_visits<'a, Spec: MCTS, R: Rng>(
+    moves: &[&'a MoveInfo<Spec>],
+    temperature: f64,
+    rng: &mut R,
+) -> Option<&'a MoveInfo<Spec>> {
+    let max_visits = moves.iter().map(|x| x.visits()).max().filter(|&x| x > 0)?;
+    if temperature == 0.0 {
+        let best: Vec<_> = moves.iter().filter(|x| x.visits() == max_visits).collect();
+        return best.choose(rng).map(|x| **x);
+    }
+    // relative to the most visited move, so large visit counts can't overflow
+    let ln_max = (max_visits as f64).ln();
+    let weights: Vec<f64> = moves
+        .iter()
+        .map(|x| match x.visits() {
+            0 => 0.0,
+            v => (((v as f64).ln() - ln_max) / temperature).exp(),
+        })
+        .collect();
+    let mut choice = rng.gen::<f64>() * weights.iter().sum::<f64>();
+    for (mov, weight) in moves.iter().zip(weights) {
+        if choice < weight {
+            return Some(mov);
+        }
+        choice -= weight;
+    }
+    // rounding error; fall back to the last move that can be chosen
+    moves.iter().rev().find(|x| x.visits() > 0).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_game::*;
+
+    // A root move with `visits` playouts, `wins` of which got a reward of one.
+    fn root_move(wins: usize, visits: usize) -> MoveInfo<AddMCTS> {
+        let mut rewards = vec![1; wins];
+        rewards.resize(visits, 0);
+        MoveInfo::with_rewards(Add::One, (), &rewards)
+    }
+
+    // The indices of `moves` in the order `selection` ranks them.
+    fn ranking(selection: MoveSelection, moves: &[MoveInfo<AddMCTS>]) -> Vec<usize> {
+        selection
+            .rank(moves)
+            .into_iter()
+            .map(|x| moves.iter().position(|y| std::ptr::eq(x, y)).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn each_selection_picks_its_move() {
+        let moves = [
+            // the most visited
+            root_move(5, 10),
+            // the best mean reward, but few visits
+            root_move(4, 4),
+            // second by both measures
+            root_move(6, 8),
+            root_move(0, 0),
+        ];
+        let best = |selection| ranking(selection, &moves)[0];
+        assert_eq!(best(MoveSelection::MaxVisits), 0);
+        assert_eq!(best(MoveSelection::MaxValue), 1);
+        assert_eq!(best(MoveSelection::RobustMax), 2);
+        assert_eq!(best(MoveSelection::SecureChild(1.0)), 1);
+        // a larger constant favours visits
+        assert_eq!(best(MoveSelection::SecureChild(3.0)), 2);
+        assert_eq!(best(MoveSelection::MostVisitsValueTiebreak), 0);
+
+        assert_eq!(ranking(MoveSelection::MaxValue, &moves), [1, 2, 0, 3]);
+        for selection in [
+            MoveSelection::MaxVisits,
+            MoveSelection::RobustMax,
+            MoveSelection::SecureChild(1.0),
+            MoveSelection::MostVisitsValueTiebreak,
+        ] {
+            // the unvisited move always comes last
+            assert_eq!(ranking(selection, &moves)[3], 3);
+        }
+    }
+
+    #[test]
+    fn value_breaks_visit_ties() {
+        let moves = [root_move(4, 10), root_move(6, 10)];
+        assert_eq!(ranking(MoveSelection::MaxVisits, &moves), [0, 1]);
+        assert_eq!(
+            ranking(MoveSelection::MostVisitsValueTiebreak, &moves),
+            [1, 0]
+        );
+    }
+}
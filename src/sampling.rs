@@ -0,0 +1,88 @@
+use rand::Rng;
+
+/// Dirichlet noise mixed into the root's move evaluations, as in AlphaZero. See
+/// `MCTSManager::root_noise`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DirichletNoise {
+    alpha: f64,
+    epsilon: f64,
+}
+
+impl DirichletNoise {
+    /// `alpha` is the concentration parameter of the distribution, and `epsilon` the
+    /// weight of the noise.
+    pub fn new(alpha: f64, epsilon: f64) -> Self {
+        assert!(alpha > 0.0, "alpha is {} (must be positive)", alpha);
+        assert!(
+            (0.0..=1.0).contains(&epsilon),
+            "epsilon is {} (must be between 0 and 1)",
+            epsilon
+        );
+        Self { alpha, epsilon }
+    }
+
+    pub fn alpha(&self) -> f64 {
+        self.alpha
+    }
+
+    pub fn epsilon(&self) -> f64 {
+        self.epsilon
+    }
+
+    /// Samples `n` weights that sum to one.
+    pub(crate) fn sample<R: Rng>(&self, n: usize, rng: &mut R) -> Vec<f64> {
+        let mut weights: Vec<f64> = (0..n).map(|_| gamma(self.alpha, rng)).collect();
+        let sum: f64 = weights.iter().sum();
+        if sum > 0.0 {
+            for x in &mut weights {
+                *x /= sum;
+            }
+        } else {
+            // every sample underflowed, which can happen for tiny alphas
+            let index = rng.gen_range(0..n);
+            weights[index] = 1.0;
+        }
+        weights
+    }
+}
+
+pub(crate) fn beta<R: Rng>(a: f64, b: f64, rng: &mut R) -> f64 {
+    let x = gamma(a, rng);
+    let y = gamma(b, rng);
+    if x + y > 0.0 {
+        x / (x + y)
+    } else {
+        // both samples underflowed
+        a / (a + b)
+    }
+}
+
+// Marsaglia and Tsang, "A Simple Method for Generating Gamma Variables" (2000)
+pub(crate) fn gamma<R: Rng>(shape: f64, rng: &mut R) -> f64 {
+    if shape < 1.0 {
+        let u: f64 = rng.gen();
+        return gamma(shape + 1.0, rng) * u.powf(1.0 / shape);
+    }
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        let (x, v) = loop {
+            let x = standard_normal(rng);
+            let v = 1.0 + c * x;
+            if v > 0.0 {
+                break (x, v * v * v);
+            }
+        };
+        let u: f64 = rng.gen();
+        if u < 1.0 - 0.0331 * x * x * x * x || u.ln() < 0.5 * x * x + d * (1.0 - v + v.ln()) {
+            return d * v;
+        }
+    }
+}
+
+// Box-Muller
+pub(crate) fn standard_normal<R: Rng>(rng: &mut R) -> f64 {
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
@@ -0,0 +1,303 @@
+use super::*;
+use atomics::*;
+use search_limits::SearchBudget;
+use std::fmt;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// How often `MCTSManager::search_with_info` reports progress.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InfoInterval {
+    Time(Duration),
+    Playouts(u64),
+}
+
+/// A snapshot of a running search, as passed to the `search_with_info` callback.
+#[derive(Debug)]
+pub struct SearchInfo<Spec: MCTS> {
+    /// Playouts finished since the search started.
+    pub playouts: u64,
+    /// The total number of nodes in the tree.
+    pub nodes: usize,
+    pub elapsed: Duration,
+    /// Nodes added to the tree per second since the search started.
+    pub nodes_per_second: f64,
+    /// The largest number of moves a playout of this search made in the tree.
+    pub max_depth: usize,
+    pub average_depth: f64,
+    /// The most visited root move, or `None` if no root move has been visited.
+    pub best_move: Option<Move<Spec>>,
+    pub best_move_visits: u64,
+    pub best_move_value: f64,
+    pub principal_variation: Vec<Move<Spec>>,
+}
+
+impl<Spec: MCTS> Display for SearchInfo<Spec>
+where
+    Move<Spec>: Display,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "playouts {} nodes {} nps {} depth {} avgdepth {:.1} time {}ms",
+            thousands_separate(self.playouts as usize),
+            thousands_separate(self.nodes),
+            thousands_separate(self.nodes_per_second as usize),
+            self.max_depth,
+            self.average_depth,
+            self.elapsed.as_millis(),
+        )?;
+        if let Some(ref best_move) = self.best_move {
+            write!(
+                f,
+                " best {} [{} visits] [{:.3} avg reward]",
+                best_move,
+                thousands_separate(self.best_move_visits as usize),
+                self.best_move_value,
+            )?;
+        }
+        write!(f, " pv")?;
+        for mov in &self.principal_variation {
+            write!(f, " {}", mov)?;
+        }
+        Ok(())
+    }
+}
+
+type InfoCallback<'a, Spec> = &'a mut (dyn FnMut(&SearchInfo<Spec>) + Send);
+
+/// Calls the progress callback of a search. Shared by every search thread; whichever
+/// thread notices that a report is due sends it.
+pub(crate) struct InfoReporter<'a, Spec: MCTS> {
+    interval: InfoInterval,
+    next: AtomicUsize,
+    start_nodes: usize,
+    callback: Mutex<InfoCallback<'a, Spec>>,
+}
+
+impl<'a, Spec: MCTS> InfoReporter<'a, Spec> {
+    pub(crate) fn new(
+        interval: InfoInterval,
+        start_nodes: usize,
+        callback: InfoCallback<'a, Spec>,
+    ) -> Self {
+        let reporter = Self {
+            interval,
+            next: AtomicUsize::new(0),
+            start_nodes,
+            callback: Mutex::new(callback),
+        };
+        reporter.next.store(reporter.step(), Ordering::Relaxed);
+        reporter
+    }
+
+    fn step(&self) -> usize {
+        let step = match self.interval {
+            InfoInterval::Time(x) => x.as_millis() as usize,
+            InfoInterval::Playouts(x) => x as usize,
+        };
+        step.max(1)
+    }
+
+    pub(crate) fn maybe_report(&self, tree: &SearchTree<Spec>, budget: &SearchBudget) {
+        let current = match self.interval {
+            InfoInterval::Time(_) => budget.elapsed().as_millis() as usize,
+            InfoInterval::Playouts(_) => budget.playouts_completed(),
+        };
+        let next = self.next.load(Ordering::Relaxed);
+        if current < next {
+            return;
+        }
+        if self
+            .next
+            .compare_exchange(
+                next,
+                current + self.step(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            )
+            .is_ok()
+        {
+            self.report(tree, budget);
+        }
+    }
+
+    pub(crate) fn report(&self, tree: &SearchTree<Spec>, budget: &SearchBudget) {
+        let elapsed = budget.elapsed();
+        let nodes = tree.num_nodes();
+        let new_nodes = nodes.saturating_sub(self.start_nodes);
+
+        let root = tree.root_search_node();
+        let best = tree.root_moves().max_by_key(|x| x.visits());
+        let best = best.filter(|x| x.visits() > 0);
+
+        let info = SearchInfo {
+            playouts: budget.playouts_completed() as u64,
+            nodes,
+            elapsed,
+            nodes_per_second: new_nodes as f64 / elapsed.as_secs_f64().max(1e-9),
+            max_depth: budget.max_depth(),
+            average_depth: budget.average_depth(),
+            best_move: best.map(|x| x.get_move().clone()),
+            best_move_visits: best.map_or(0, |x| x.visits()),
+            best_move_value: best.map_or(0.0, |x| x.mean_reward()),
+            // The tree may contain cycles, so the PV is bounded. It can't be longer than
+            // the number of nodes without repeating one.
+            principal_variation: tree
+                .principal_variation(root, nodes)
+                .into_iter()
+                .map(|x| x.get_move().clone())
+                .collect(),
+        };
+        (self.callback.lock().unwrap())(&info);
+    }
+}
+
+/// One measurement of `MCTSManager::perf_test`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PerfSample {
+    /// Time since the test started, at the end of this sample.
+    pub elapsed: Duration,
+    /// The measured length of this sample.
+    pub duration: Duration,
+    /// Nodes added to the tree during this sample.
+    pub nodes: usize,
+    /// Playouts finished during this sample.
+    pub playouts: u64,
+}
+
+impl PerfSample {
+    pub fn nodes_per_second(&self) -> f64 {
+        self.nodes as f64 / self.duration.as_secs_f64()
+    }
+
+    pub fn playouts_per_second(&self) -> f64 {
+        self.playouts as f64 / self.duration.as_secs_f64()
+    }
+}
+
+impl Display for PerfSample {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes/sec, {} playouts/sec",
+            thousands_separate(self.nodes_per_second() as usize),
+            thousands_separate(self.playouts_per_second() as usize),
+        )
+    }
+}
+
+/// The z-score of the confidence intervals in `MoveAnalysis` (95%).
+const CONFIDENCE_Z: f64 = 1.96;
+
+/// One line of `MCTSManager::analysis`: a root move, its statistics, and the principal
+/// variation that follows it.
+pub struct MoveAnalysis<'a, Spec: MCTS> {
+    pub mov: Move<Spec>,
+    pub visits: u64,
+    pub mean: f64,
+    /// The tree policy's evaluation of the move, such as its prior probability.
+    pub prior: &'a MoveEvaluation<Spec>,
+    /// A 95% confidence interval for the mean reward, from the normal approximation.
+    /// Unbounded if the move has fewer than two visits.
+    pub confidence_interval: (f64, f64),
+    /// The most visited line after `mov`, not including `mov` itself.
+    pub principal_variation: Vec<Move<Spec>>,
+}
+
+impl<'a, Spec: MCTS> MoveAnalysis<'a, Spec> {
+    pub(crate) fn new(
+        tree: &'a SearchTree<Spec>,
+        node: &'a SearchNode<Spec>,
+        info: &'a MoveInfo<Spec>,
+        pv_length: usize,
+    ) -> Self {
+        let visits = info.visits();
+        let mean = info.mean_reward();
+        // a single sample says nothing about the variance
+        let half_width = if visits < 2 {
+            f64::INFINITY
+        } else {
+            CONFIDENCE_Z * (info.reward_variance() / visits as f64).sqrt()
+        };
+        let principal_variation = match tree.child_node(node, info) {
+            Some(child) => tree
+                .principal_variation(child, pv_length)
+                .into_iter()
+                .map(|x| x.get_move().clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        Self {
+            mov: info.get_move().clone(),
+            visits,
+            mean,
+            prior: info.move_evaluation(),
+            confidence_interval: (mean - half_width, mean + half_width),
+            principal_variation,
+        }
+    }
+}
+
+impl<'a, Spec: MCTS> Display for MoveAnalysis<'a, Spec>
+where
+    Move<Spec>: Display,
+    MoveEvaluation<Spec>: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} [{} visits] [{:.3} avg reward] [{:.3}, {:.3}] [prior {:?}] pv",
+            self.mov,
+            thousands_separate(self.visits as usize),
+            self.mean,
+            self.confidence_interval.0,
+            self.confidence_interval.1,
+            self.prior,
+        )?;
+        for mov in &self.principal_variation {
+            write!(f, " {}", mov)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use search_limits::SearchLimits;
+    use test_game::*;
+
+    #[test]
+    fn time_interval_reports() {
+        let mut manager = manager();
+        let mut reports = Vec::new();
+        let limits = SearchLimits::new().time(Duration::from_millis(50));
+        let interval = InfoInterval::Time(Duration::from_millis(1));
+        manager
+            .search_with_info(limits, 1, interval, |info| reports.push(info.elapsed))
+            .unwrap();
+        // at least one report before the final one
+        assert!(reports.len() >= 2, "{:?}", reports);
+        assert!(reports[0] < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn report_before_first_playout_has_pv() {
+        let mut manager = manager();
+        manager.playout_n(500).unwrap();
+        let expected = manager.principal_variation(100);
+        assert!(!expected.is_empty());
+
+        let mut principal_variation = Vec::new();
+        let mut callback = |info: &SearchInfo<AddMCTS>| {
+            principal_variation = info.principal_variation.clone();
+        };
+        let tree = manager.tree();
+        let reporter =
+            InfoReporter::new(InfoInterval::Playouts(1), tree.num_nodes(), &mut callback);
+        reporter.report(tree, &SearchBudget::new(SearchLimits::new()));
+        assert_eq!(principal_variation, expected);
+    }
+}
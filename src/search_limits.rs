@@ -0,0 +1,272 @@
+use super::*;
+use atomics::*;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A budget for `MCTSManager::search`. The search stops as soon as any of the limits
+/// is reached. A limit that isn't set is unbounded, so a search with no limits at all
+/// only stops when `MCTS::node_limit` is reached.
+///
+/// ```
+/// use mcts::SearchLimits;
+/// use std::time::Duration;
+///
+/// let limits = SearchLimits::new()
+///     .playouts(100_000)
+///     .time(Duration::from_millis(500))
+///     .memory(1 << 30);
+/// ```
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchLimits {
+    playouts: Option<u64>,
+    time: Option<Duration>,
+    nodes: Option<usize>,
+    memory: Option<usize>,
+}
+
+impl SearchLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop after this many playouts have been started.
+    pub fn playouts(mut self, playouts: u64) -> Self {
+        self.playouts = Some(playouts);
+        self
+    }
+
+    /// Stop once this much wall-clock time has passed since the search began.
+    pub fn time(mut self, time: Duration) -> Self {
+        self.time = Some(time);
+        self
+    }
+
+    /// Stop once the tree has this many nodes. `MCTS::node_limit` is always respected
+    /// as well.
+    pub fn nodes(mut self, nodes: usize) -> Self {
+        self.nodes = Some(nodes);
+        self
+    }
+
+    /// Stop once the tree uses approximately this many bytes (see
+    /// `SearchTree::memory_usage`).
+    pub fn memory(mut self, bytes: usize) -> Self {
+        self.memory = Some(bytes);
+        self
+    }
+}
+
+/// The limit that ended a search.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum StopReason {
+    Playouts,
+    Time,
+    /// Either `SearchLimits::nodes` or `MCTS::node_limit` was reached.
+    Nodes,
+    Memory,
+}
+
+/// The shared state of a running search, checked by every thread before each playout.
+pub(crate) struct SearchBudget {
+    limits: SearchLimits,
+    start: Instant,
+    deadline: Option<Instant>,
+    playouts_started: AtomicUsize,
+    stopped: AtomicBool,
+    reason: Mutex<Option<StopReason>>,
+    error: Mutex<Option<SearchError>>,
+
+    playouts_completed: AtomicUsize,
+    total_depth: AtomicUsize,
+    max_depth: AtomicUsize,
+}
+
+impl SearchBudget {
+    pub(crate) fn new(limits: SearchLimits) -> Self {
+        let start = Instant::now();
+        Self {
+            limits,
+            start,
+            deadline: limits.time.map(|x| start + x),
+            playouts_started: AtomicUsize::new(0),
+            stopped: AtomicBool::new(false),
+            reason: Mutex::new(None),
+            error: Mutex::new(None),
+            playouts_completed: AtomicUsize::new(0),
+            total_depth: AtomicUsize::new(0),
+            max_depth: AtomicUsize::new(0),
+        }
+    }
+
+    /// Stops every thread of the search. Only the first reason is kept.
+    pub(crate) fn stop(&self, reason: StopReason) {
+        let mut stored = self.reason.lock().unwrap();
+        if stored.is_none() {
+            *stored = Some(reason);
+        }
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    /// Stops every thread of the search because a playout failed. Only the first error
+    /// is kept.
+    pub(crate) fn fail(&self, error: SearchError) {
+        let mut stored = self.error.lock().unwrap();
+        if stored.is_none() {
+            *stored = Some(error);
+        }
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    /// Stops every thread of the search without a reason, because one of them panicked.
+    /// Whoever waits for the threads resumes the panic.
+    pub(crate) fn abort(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub(crate) fn error(&self) -> Option<SearchError> {
+        *self.error.lock().unwrap()
+    }
+
+    pub(crate) fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Returns `true` if another playout may be started.
+    pub(crate) fn start_playout<Spec: MCTS>(&self, tree: &SearchTree<Spec>) -> bool {
+        if self.is_stopped() {
+            return false;
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                self.stop(StopReason::Time);
+                return false;
+            }
+        }
+        if let Some(nodes) = self.limits.nodes {
+            if tree.num_nodes() >= nodes {
+                self.stop(StopReason::Nodes);
+                return false;
+            }
+        }
+        if let Some(memory) = self.limits.memory {
+            if tree.memory_usage() >= memory {
+                self.stop(StopReason::Memory);
+                return false;
+            }
+        }
+        let started = self.playouts_started.fetch_add(1, Ordering::SeqCst) as u64;
+        if let Some(playouts) = self.limits.playouts {
+            if started >= playouts {
+                self.stop(StopReason::Playouts);
+                return false;
+            }
+        }
+        true
+    }
+
+    pub(crate) fn stop_reason(&self) -> Option<StopReason> {
+        *self.reason.lock().unwrap()
+    }
+
+    /// Records a finished playout that made `depth` moves in the tree.
+    pub(crate) fn finish_playout(&self, depth: usize) {
+        self.playouts_completed.fetch_add(1, Ordering::Relaxed);
+        self.total_depth.fetch_add(depth, Ordering::Relaxed);
+        self.max_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    pub(crate) fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+
+    pub(crate) fn playouts_completed(&self) -> usize {
+        self.playouts_completed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn max_depth(&self) -> usize {
+        self.max_depth.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn average_depth(&self) -> f64 {
+        let playouts = self.playouts_completed();
+        if playouts == 0 {
+            0.0
+        } else {
+            self.total_depth.load(Ordering::Relaxed) as f64 / playouts as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_game::*;
+
+    fn root_visits(manager: &MCTSManager<AddMCTS>) -> u64 {
+        manager.tree().root_moves().map(|x| x.visits()).sum()
+    }
+
+    #[test]
+    fn playout_limit() {
+        for num_threads in [1, 3] {
+            let mut manager = manager();
+            let limits = SearchLimits::new().playouts(500);
+            assert_eq!(
+                manager.search(limits, num_threads),
+                Ok(StopReason::Playouts)
+            );
+            assert_eq!(root_visits(&manager), 500);
+        }
+    }
+
+    #[test]
+    fn time_limit() {
+        for num_threads in [1, 3] {
+            let mut manager = manager();
+            let start = Instant::now();
+            let limits = SearchLimits::new().time(Duration::from_millis(20));
+            assert_eq!(manager.search(limits, num_threads), Ok(StopReason::Time));
+            assert!(start.elapsed() >= Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn node_limit() {
+        for num_threads in [1, 3] {
+            let mut manager = manager();
+            let limits = SearchLimits::new().nodes(10).time(Duration::from_secs(10));
+            assert_eq!(manager.search(limits, num_threads), Ok(StopReason::Nodes));
+            // each thread may create one more node after the limit is checked
+            let num_nodes = manager.tree().num_nodes();
+            assert!(
+                num_nodes >= 10 && num_nodes < 10 + num_threads,
+                "{}",
+                num_nodes
+            );
+        }
+    }
+
+    #[test]
+    fn memory_limit() {
+        for num_threads in [1, 3] {
+            let mut manager = manager();
+            let memory = 5 * manager.tree().memory_usage();
+            let limits = SearchLimits::new()
+                .memory(memory)
+                .time(Duration::from_secs(10));
+            assert_eq!(manager.search(limits, num_threads), Ok(StopReason::Memory));
+            assert!(manager.tree().memory_usage() >= memory);
+        }
+    }
+
+    #[test]
+    fn first_limit_reached_wins() {
+        let mut manager = manager();
+        let limits = SearchLimits::new()
+            .playouts(1_000_000)
+            .nodes(5)
+            .time(Duration::from_secs(10));
+        assert_eq!(manager.search(limits, 1), Ok(StopReason::Nodes));
+        assert!(root_visits(&manager) < 1_000_000);
+    }
+}
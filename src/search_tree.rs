@@ -5,9 +5,11 @@ use std;
 use super::*;
 use atomics::*;
 use smallvec::SmallVec;
+use std::collections::HashSet;
 use std::fmt;
 use std::fmt::{Debug, Display, Formatter};
-use std::ptr::{null, null_mut};
+use std::mem;
+use std::ptr::null_mut;
 use std::sync::Mutex;
 
 use tree_policy::TreePolicy;
@@ -23,7 +25,14 @@ pub struct SearchTree<Spec: MCTS> {
     manager: Spec,
 
     num_nodes: AtomicUsize,
+    memory_usage: AtomicUsize,
     orphaned: Mutex<Vec<Box<SearchNode<Spec>>>>,
+    // previous roots, waiting for `collect_garbage`
+    detached: Vec<Box<SearchNode<Spec>>>,
+    // which of the root's moves the search may choose, if restricted
+    root_filter: Option<Vec<bool>>,
+    // the evaluations of the root's moves from before `add_root_noise`, by move index
+    clean_root_evaluations: Vec<(usize, MoveEvaluation<Spec>)>,
     transposition_table_hits: AtomicUsize,
     delayed_transposition_table_hits: AtomicUsize,
     expansion_contention_events: AtomicUsize,
@@ -33,6 +42,8 @@ pub struct SearchTree<Spec: MCTS> {
 pub struct NodeStats {
     visits: AtomicUsize,
     sum_evaluations: AtomicI64,
+    // the bits of an f64, since the squares can overflow an i64
+    sum_squared_evaluations: AtomicU64,
 }
 
 pub struct MoveInfo<Spec: MCTS> {
@@ -91,9 +102,38 @@ impl<Spec: MCTS> MoveInfo<Spec> {
         self.stats.sum_evaluations.load(Ordering::Relaxed) as i64
     }
 
-    pub fn child(&self) -> Option<NodeHandle<Spec>> {
+    /// The average reward of the playouts through this move, or zero if it hasn't
+    /// been visited.
+    pub fn mean_reward(&self) -> f64 {
+        let visits = self.visits();
+        if visits == 0 {
+            0.0
+        } else {
+            self.sum_rewards() as f64 / visits as f64
+        }
+    }
+
+    /// The sum of the squares of the rewards of the playouts through this move.
+    pub fn sum_squared_rewards(&self) -> f64 {
+        self.stats.sum_squared_evaluations()
+    }
+
+    /// The sample variance of the rewards of the playouts through this move, or zero if
+    /// it has fewer than two visits. Playouts that are still running count as visits
+    /// without a reward, so this is only exact when no search is running.
+    pub fn reward_variance(&self) -> f64 {
+        let visits = self.visits();
+        if visits < 2 {
+            return 0.0;
+        }
+        let n = visits as f64;
+        let mean = self.sum_rewards() as f64 / n;
+        ((self.sum_squared_rewards() - n * mean * mean) / (n - 1.0)).max(0.0)
+    }
+
+    pub fn child(&self) -> Option<NodeHandle<'_, Spec>> {
         let ptr = self.child.load(Ordering::Relaxed);
-        if ptr == null_mut() {
+        if ptr.is_null() {
             None
         } else {
             unsafe { Some(NodeHandle { node: &*ptr }) }
@@ -159,9 +199,9 @@ impl<Spec: MCTS> Drop for MoveInfo<Spec> {
             return;
         }
         let ptr = self.child.load(Ordering::SeqCst);
-        if ptr != null_mut() {
+        if !ptr.is_null() {
             unsafe {
-                Box::from_raw(ptr);
+                drop(Box::from_raw(ptr));
             }
         }
     }
@@ -174,18 +214,42 @@ fn create_node<Spec: MCTS>(
     handle: Option<SearchHandle<Spec>>,
 ) -> SearchNode<Spec> {
     let moves = state.available_moves();
-    let (move_eval, state_eval) = eval.evaluate_new_state(&state, &moves, handle);
+    let (move_eval, state_eval) = eval.evaluate_new_state(state, &moves, handle);
     policy.validate_evaluations(&move_eval);
     let moves = moves
         .into_iter()
-        .zip(move_eval.into_iter())
+        .zip(move_eval)
         .map(|(m, e)| MoveInfo::new(m, e))
         .collect();
     SearchNode::new(state.clone(), moves, state_eval)
 }
 
+fn node_memory_usage<Spec: MCTS>(node: &SearchNode<Spec>) -> usize {
+    mem::size_of::<SearchNode<Spec>>() + node.moves.capacity() * mem::size_of::<MoveInfo<Spec>>()
+}
+
+fn find_owner<Spec: MCTS>(
+    root: &SearchNode<Spec>,
+    target: *const SearchNode<Spec>,
+) -> Option<&MoveInfo<Spec>> {
+    let mut stack = vec![root];
+    while let Some(node) = stack.pop() {
+        for info in &node.moves {
+            if !info.owned.load(Ordering::SeqCst) {
+                continue;
+            }
+            let child = info.child.load(Ordering::SeqCst) as *const SearchNode<Spec>;
+            if std::ptr::eq(child, target) {
+                return Some(info);
+            }
+            stack.push(unsafe { &*child });
+        }
+    }
+    None
+}
+
 fn is_cycle<T>(past: &[&T], current: &T) -> bool {
-    past.iter().any(|x| *x as *const T == current as *const T)
+    past.iter().any(|x| std::ptr::eq(*x, current))
 }
 
 impl<Spec: MCTS> SearchTree<Spec> {
@@ -199,6 +263,7 @@ impl<Spec: MCTS> SearchTree<Spec> {
         let root_node = Box::new(create_node(&eval, &tree_policy, &state, None));
 
         table.insert(&state, root_node.as_ref());
+        let memory_usage = node_memory_usage(&root_node);
 
         Self {
             root_state: state.clone(),
@@ -208,69 +273,102 @@ impl<Spec: MCTS> SearchTree<Spec> {
             eval,
             table,
             num_nodes: 1.into(),
+            memory_usage: memory_usage.into(),
             orphaned: Mutex::new(Vec::new()),
+            detached: Vec::new(),
+            root_filter: None,
+            clean_root_evaluations: Vec::new(),
             transposition_table_hits: 0.into(),
             delayed_transposition_table_hits: 0.into(),
             expansion_contention_events: 0.into(),
         }
     }
 
-    pub fn reset(self) -> Self {
-        Self::new(
-            self.root_state,
-            self.manager,
-            self.tree_policy,
-            self.eval,
-            self.table,
-        )
+    /// Discards every node and clears the transposition table, then starts a new
+    /// search from `state`. Like `advance`, this keeps the old nodes if the table
+    /// doesn't support `TranspositionTable::remove`.
+    pub fn reset(&mut self, state: Spec::State) {
+        self.remove_root_noise();
+        self.table.clear();
+        self.root_filter = None;
+        let root = create_node(&self.eval, &self.tree_policy, &state, None);
+        self.num_nodes.fetch_add(1, Ordering::SeqCst);
+        self.memory_usage
+            .fetch_add(node_memory_usage(&root), Ordering::SeqCst);
+        let old_root = mem::replace(&mut self.root_node, Box::new(root));
+        self.detached.push(old_root);
+        self.collect_garbage();
+        self.table.insert(&state, &self.root_node);
+        self.root_state = state;
+
+        *self.transposition_table_hits.get_mut() = 0;
+        *self.delayed_transposition_table_hits.get_mut() = 0;
+        *self.expansion_contention_events.get_mut() = 0;
     }
 
     pub fn spec(&self) -> &Spec {
         &self.manager
     }
 
+    pub fn tree_policy(&self) -> &Spec::TreePolicy {
+        &self.tree_policy
+    }
+
     pub fn num_nodes(&self) -> usize {
         self.num_nodes.load(Ordering::SeqCst)
     }
 
+    /// Approximate number of bytes used by the nodes of the tree. Heap memory owned
+    /// by states, moves and evaluations is not counted.
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage.load(Ordering::Relaxed)
+    }
+
     pub fn get_node(&self, state: &Spec::State) -> Option<&SearchNode<Spec>> {
         self.table.lookup(state)
     }
 
+    /// Runs one playout. A playout that fails leaves the statistics of the tree as they
+    /// were before it started.
+    pub fn playout(&self, tld: &mut ThreadData<Spec>) -> Result<(), SearchError> {
+        self.playout_with_depth(tld).map(|_| ())
+    }
+
+    /// Like `playout`, but returns the number of moves the playout made in the tree.
     #[inline(never)]
-    pub fn playout(&self, mut state: Spec::State, tld: &mut ThreadData<Spec>) -> bool {
+    pub(crate) fn playout_with_depth(
+        &self,
+        tld: &mut ThreadData<Spec>,
+    ) -> Result<usize, SearchError> {
         const LARGE_DEPTH: usize = 64;
         let sentinel = IncreaseSentinel::new(&self.num_nodes);
         if sentinel.num_nodes >= self.manager.node_limit() {
-            return false;
+            return Err(SearchError::NodeLimitReached);
         }
         let mut path: SmallVec<[&MoveInfo<Spec>; LARGE_DEPTH]> = SmallVec::new();
         let mut node_path: SmallVec<[&SearchNode<Spec>; LARGE_DEPTH]> = SmallVec::new();
         let mut players: SmallVec<[Player<Spec>; LARGE_DEPTH]> = SmallVec::new();
         let mut did_we_create = false;
 
-        let mut node = self.table.lookup(&state).unwrap();
+        let mut state = self.root_state.clone();
+        let mut node: &SearchNode<Spec> = &self.root_node;
 
         loop {
-            assert_eq!(state, node.state);
-
-            if node.moves.len() == 0 {
+            if node.moves.is_empty() {
                 break;
             }
             if path.len() >= self.manager.max_playout_length() {
-                break;
+                self.abandon_playout(&path, &node_path);
+                return Err(SearchError::PlayoutTooLong);
             }
 
             let choice = self
                 .tree_policy
-                .choose_child(node.moves.iter(), self.make_handle(node, tld));
+                .choose_child(self.selectable_moves(node), self.make_handle(node, tld));
 
             choice.stats.down(&self.manager);
             players.push(state.current_player());
             path.push(choice);
-            assert!(path.len() <= self.manager.max_playout_length(),
-                "playout length exceeded maximum of {} (maybe the transposition table is creating an infinite loop?)",
-                self.manager.max_playout_length());
             state.make_move(&choice.mov);
             let (new_node, new_did_we_create) = self.descend(&state, choice, node, tld);
             node = new_node;
@@ -279,7 +377,8 @@ impl<Spec: MCTS> SearchTree<Spec> {
                 CycleBehaviour::Ignore => (),
                 CycleBehaviour::PanicWhenCycleDetected => {
                     if is_cycle(&node_path, node) {
-                        panic!("cycle detected! you should do one of the following:\n- make states acyclic\n- remove transposition table\n- change cycle_behaviour()");
+                        self.abandon_playout(&path, &node_path);
+                        return Err(SearchError::CycleDetected);
                     }
                 }
                 CycleBehaviour::UseCurrentEvalWhenCycleDetected => {
@@ -290,10 +389,14 @@ impl<Spec: MCTS> SearchTree<Spec> {
                 CycleBehaviour::UseThisEvalWhenCycleDetected(e) => {
                     if is_cycle(&node_path, node) {
                         self.finish_playout(&path, &node_path, &players, tld, &e);
-                        return true;
+                        return Ok(path.len());
                     }
                 }
             };
+            if node.state != state {
+                self.abandon_playout(&path, &node_path);
+                return Err(SearchError::StateMismatch);
+            }
             node_path.push(node);
             node.stats.down(&self.manager);
             if node.stats.visits.load(Ordering::Relaxed) as u64
@@ -313,7 +416,7 @@ impl<Spec: MCTS> SearchTree<Spec> {
         };
         let evaln = new_evaln.as_ref().unwrap_or(&node.evaln);
         self.finish_playout(&path, &node_path, &players, tld, evaln);
-        true
+        Ok(path.len())
     }
 
     pub fn descend<'a, 'b>(
@@ -323,8 +426,8 @@ impl<Spec: MCTS> SearchTree<Spec> {
         current_node: &'b SearchNode<Spec>,
         tld: &'b mut ThreadData<Spec>,
     ) -> (&'a SearchNode<Spec>, bool) {
-        let child = choice.child.load(Ordering::Relaxed) as *const _;
-        if child != null() {
+        let child = choice.child.load(Ordering::Relaxed) as *const SearchNode<Spec>;
+        if !child.is_null() {
             // println!("NOT NULL");
             return unsafe { (&*child, false) };
         }
@@ -338,9 +441,9 @@ impl<Spec: MCTS> SearchTree<Spec> {
             ) {
                 Ok(val) => val,
                 Err(val) => val,
-            } as *const _;
+            } as *const SearchNode<Spec>;
 
-            if child == null() {
+            if child.is_null() {
                 self.transposition_table_hits
                     .fetch_add(1, Ordering::Relaxed);
                 return (node, false);
@@ -364,13 +467,13 @@ impl<Spec: MCTS> SearchTree<Spec> {
         ) {
             Ok(val) => val,
             Err(val) => val,
-        } as *const _;
+        } as *const SearchNode<Spec>;
 
-        if other_child != null_mut() {
+        if !other_child.is_null() {
             self.expansion_contention_events
                 .fetch_add(1, Ordering::Relaxed);
             unsafe {
-                Box::from_raw(created);
+                drop(Box::from_raw(created));
                 return (&*other_child, false);
             }
         }
@@ -379,10 +482,10 @@ impl<Spec: MCTS> SearchTree<Spec> {
                 .fetch_add(1, Ordering::Relaxed);
             let existing_ptr = existing as *const _ as *mut _;
             choice.child.store(existing_ptr, Ordering::Relaxed);
-            self.orphaned
-                .lock()
-                .unwrap()
-                .push(unsafe { Box::from_raw(created) });
+            let created = unsafe { Box::from_raw(created) };
+            self.memory_usage
+                .fetch_add(node_memory_usage(&created), Ordering::Relaxed);
+            self.orphaned.lock().unwrap().push(created);
             return (existing, false);
         }
 
@@ -390,7 +493,127 @@ impl<Spec: MCTS> SearchTree<Spec> {
 
         choice.owned.store(true, Ordering::Relaxed);
         self.num_nodes.fetch_add(1, Ordering::Relaxed);
-        unsafe { (&*created, true) }
+        let created = unsafe { &*created };
+        self.memory_usage
+            .fetch_add(node_memory_usage(created), Ordering::Relaxed);
+        (created, true)
+    }
+
+    /// Makes the child reached by `mov` the new root, so that later playouts and
+    /// statistics start from the position after `mov`. The child is created if the
+    /// search never reached it. Panics if `mov` isn't one of the root's moves.
+    pub fn advance(&mut self, mov: &Move<Spec>, tld: &mut ThreadData<Spec>) {
+        self.remove_root_noise();
+        let mut state = self.root_state.clone();
+        state.make_move(mov);
+
+        let new_root = {
+            let root: &SearchNode<Spec> = &self.root_node;
+            let choice = root
+                .moves
+                .iter()
+                .find(|x| x.mov == *mov)
+                .unwrap_or_else(|| panic!("{:?} is not one of the root's moves", mov));
+            self.descend(&state, choice, root, tld).0 as *const SearchNode<Spec>
+        };
+        self.root_state = state;
+        self.root_filter = None;
+
+        if std::ptr::eq(new_root, self.root_node.as_ref()) {
+            return;
+        }
+
+        // Every node is either a root, an orphan or owned by exactly one `MoveInfo`, so
+        // following owned pointers from each of those finds the owner of the new root.
+        // Its owner may be below an orphan if other threads expanded the orphan before
+        // it lost its race to the table.
+        let new_root = match self
+            .detached
+            .iter()
+            .position(|x| std::ptr::eq(x.as_ref(), new_root))
+        {
+            Some(index) => self.detached.swap_remove(index),
+            None => {
+                let owner = std::iter::once(&self.root_node)
+                    .chain(self.detached.iter())
+                    .chain(self.orphaned.get_mut().unwrap().iter())
+                    .filter_map(|root| find_owner(root, new_root))
+                    .next()
+                    .expect("new root has no owner");
+                owner.owned.store(false, Ordering::SeqCst);
+                unsafe { Box::from_raw(new_root as *mut SearchNode<Spec>) }
+            }
+        };
+        let old_root = mem::replace(&mut self.root_node, new_root);
+        self.detached.push(old_root);
+
+        self.collect_garbage();
+    }
+
+    /// Frees every node that can no longer be reached from the root, and removes
+    /// those nodes from the transposition table. `advance` calls this after every move.
+    /// Does nothing if the table doesn't support `TranspositionTable::remove`.
+    pub fn collect_garbage(&mut self) {
+        if !self.table.supports_remove() {
+            return;
+        }
+        // Mark every node reachable from the root. Ownership is rebuilt along the way:
+        // each reachable node becomes owned by the first `MoveInfo` that reaches it, so
+        // the owned pointers form a tree below the root again.
+        let root: &SearchNode<Spec> = &self.root_node;
+        let mut reachable = HashSet::new();
+        reachable.insert(root as *const SearchNode<Spec>);
+        let mut stack = vec![root];
+        while let Some(node) = stack.pop() {
+            for info in &node.moves {
+                let child = info.child.load(Ordering::SeqCst) as *const SearchNode<Spec>;
+                if child.is_null() {
+                    continue;
+                }
+                let first = reachable.insert(child);
+                info.owned.store(first, Ordering::SeqCst);
+                if first {
+                    stack.push(unsafe { &*child });
+                }
+            }
+        }
+
+        // Every other node hangs off a detached root or an orphan through owned pointers
+        // (other threads may briefly descend into an orphan before it is replaced).
+        // Owned pointers from there to reachable nodes are released, so that dropping
+        // the detached roots and orphans frees exactly the unreachable nodes.
+        let orphaned = mem::take(self.orphaned.get_mut().unwrap());
+        // orphans were never counted in `num_nodes`
+        let num_orphans = orphaned.len();
+        let mut num_freed = 0;
+        let mut memory_freed = 0;
+        for detached in self.detached.drain(..).chain(orphaned) {
+            if reachable.contains(&(detached.as_ref() as *const SearchNode<Spec>)) {
+                // now owned by the `MoveInfo` that reached it first
+                mem::forget(detached);
+                continue;
+            }
+            let mut stack: Vec<&SearchNode<Spec>> = vec![&detached];
+            while let Some(node) = stack.pop() {
+                num_freed += 1;
+                memory_freed += node_memory_usage(node);
+                self.table.remove(&node.state, node);
+                for info in &node.moves {
+                    if !info.owned.load(Ordering::SeqCst) {
+                        continue;
+                    }
+                    let child = info.child.load(Ordering::SeqCst) as *const SearchNode<Spec>;
+                    if reachable.contains(&child) {
+                        info.owned.store(false, Ordering::SeqCst);
+                    } else {
+                        stack.push(unsafe { &*child });
+                    }
+                }
+            }
+        }
+        self.num_nodes.fetch_add(num_orphans, Ordering::SeqCst);
+        self.num_nodes.fetch_sub(num_freed, Ordering::SeqCst);
+        self.memory_usage.fetch_sub(memory_freed, Ordering::SeqCst);
     }
 
     fn finish_playout(
@@ -401,21 +624,50 @@ impl<Spec: MCTS> SearchTree<Spec> {
         tld: &mut ThreadData<Spec>,
         evaln: &StateEvaluation<Spec>,
     ) {
-        for ((move_info, player), node) in
-            path.iter().zip(players.iter()).zip(node_path.iter()).rev()
+        for (i, ((move_info, player), node)) in path
+            .iter()
+            .zip(players.iter())
+            .zip(node_path.iter())
+            .enumerate()
+            .rev()
         {
             let evaln_value = self.eval.interpret_evaluation_for_player(evaln, player);
             node.stats.up(&self.manager, evaln_value);
             move_info.stats.replace(&node.stats);
+            let parent = if i == 0 {
+                self.root_node.as_ref()
+            } else {
+                node_path[i - 1]
+            };
+            let playout = PlayoutMoves {
+                moves: &path[i..],
+                players: &players[i..],
+            };
+            self.tree_policy.on_backpropagation(
+                self.selectable_moves(parent),
+                playout,
+                evaln_value,
+                self.make_handle(parent, tld),
+            );
             unsafe {
                 self.manager.on_backpropagation(
-                    &evaln,
+                    evaln,
                     self.make_handle(&*move_info.child.load(Ordering::Relaxed), tld),
                 );
             }
         }
         self.manager
-            .on_backpropagation(&evaln, self.make_handle(&self.root_node, tld));
+            .on_backpropagation(evaln, self.make_handle(&self.root_node, tld));
+    }
+
+    /// Takes back the visits and virtual losses a failed playout added.
+    fn abandon_playout(&self, path: &[&MoveInfo<Spec>], node_path: &[&SearchNode<Spec>]) {
+        for move_info in path {
+            move_info.stats.revert(&self.manager);
+        }
+        for node in node_path {
+            node.stats.revert(&self.manager);
+        }
     }
 
     fn make_handle<'a>(
@@ -427,13 +679,160 @@ impl<Spec: MCTS> SearchTree<Spec> {
             node,
             tld,
             manager: &self.manager,
+            is_root: std::ptr::eq(node, self.root_node.as_ref()),
+        }
+    }
+
+    /// Follows the most visited move from `node` for up to `num_moves` moves. Child
+    /// pointers that haven't been set yet are resolved through the transposition table.
+    /// The walk stops at the first node that hasn't been expanded.
+    pub fn principal_variation<'a>(
+        &'a self,
+        node: &'a SearchNode<Spec>,
+        num_moves: usize,
+    ) -> Vec<MoveInfoHandle<'a, Spec>> {
+        let mut result = Vec::new();
+        let mut node = node;
+        while result.len() < num_moves {
+            let choice = match self.selectable_moves(node).max_by_key(|x| x.visits()) {
+                Some(choice) if choice.visits() > 0 => choice,
+                _ => break,
+            };
+            result.push(choice);
+            node = match self.child_node(node, choice) {
+                Some(child) => child,
+                None => break,
+            };
+        }
+        result
+    }
+
+    /// The node `choice` leads to from `node`, resolving child pointers that haven't
+    /// been set yet through the transposition table.
+    pub(crate) fn child_node<'a>(
+        &'a self,
+        node: &'a SearchNode<Spec>,
+        choice: &'a MoveInfo<Spec>,
+    ) -> Option<&'a SearchNode<Spec>> {
+        let child = choice.child.load(Ordering::Relaxed) as *const SearchNode<Spec>;
+        if child.is_null() {
+            let mut state = node.state.clone();
+            state.make_move(&choice.mov);
+            self.table.lookup(&state)
+        } else {
+            unsafe { Some(&*child) }
+        }
+    }
+
+    /// Makes the search choose only among `moves` at the root. The rest of the tree
+    /// isn't affected. The restriction is lifted when the root changes. Panics if
+    /// `moves` is empty or contains a move the root doesn't have.
+    pub fn restrict_root_moves(&mut self, moves: &[Move<Spec>]) {
+        assert!(!moves.is_empty(), "at least one root move must be allowed");
+        let mut allowed = vec![false; self.root_node.moves.len()];
+        for mov in moves {
+            let index = self
+                .root_node
+                .moves
+                .iter()
+                .position(|x| x.mov == *mov)
+                .unwrap_or_else(|| panic!("{:?} is not one of the root's moves", mov));
+            allowed[index] = true;
+        }
+        self.root_filter = Some(allowed);
+    }
+
+    pub fn clear_root_restriction(&mut self) {
+        self.root_filter = None;
+    }
+
+    /// Mixes `noise`, one weight per root move, into the evaluations of the root's moves
+    /// through `TreePolicy::noisy_evaluation`. Noise added earlier is removed first, and
+    /// the clean evaluations are put back when the root changes.
+    pub fn add_root_noise(&mut self, noise: &[f64], epsilon: f64) {
+        assert_eq!(noise.len(), self.root_node.moves.len());
+        self.remove_root_noise();
+        for (i, (info, &x)) in self.root_node.moves.iter_mut().zip(noise).enumerate() {
+            if let Some(noisy) =
+                self.tree_policy
+                    .noisy_evaluation(&info.move_evaluation, x, epsilon)
+            {
+                let clean = mem::replace(&mut info.move_evaluation, noisy);
+                self.clean_root_evaluations.push((i, clean));
+            }
+        }
+    }
+
+    /// Puts back the evaluations of the root's moves from before `add_root_noise`.
+    pub fn remove_root_noise(&mut self) {
+        for (i, clean) in self.clean_root_evaluations.drain(..) {
+            self.root_node.moves[i].move_evaluation = clean;
         }
     }
 
+    /// The root moves the search may choose: all of them, unless restricted by
+    /// `restrict_root_moves`.
+    pub fn root_moves(&self) -> impl Iterator<Item = &MoveInfo<Spec>> + Clone {
+        self.selectable_moves(&self.root_node)
+    }
+
+    fn selectable_moves<'a>(
+        &'a self,
+        node: &'a SearchNode<Spec>,
+    ) -> impl Iterator<Item = &'a MoveInfo<Spec>> + Clone {
+        let filter = if std::ptr::eq(node, self.root_node.as_ref()) {
+            self.root_filter.as_deref()
+        } else {
+            None
+        };
+        node.moves
+            .iter()
+            .enumerate()
+            .filter(move |&(i, _)| match filter {
+                Some(allowed) => allowed[i],
+                None => true,
+            })
+            .map(|(_, x)| x)
+    }
+
+    /// The probability of each root move under the tree policy's mixed strategy, or
+    /// under the proportion of visits for policies that don't have one.
+    pub fn root_strategy(&self) -> Vec<(&MoveInfo<Spec>, f64)> {
+        let node = self.root_node.as_ref();
+        let moves = self.root_moves();
+        let probabilities = self
+            .tree_policy
+            .mixed_strategy(moves.clone(), NodeHandle { node })
+            .unwrap_or_else(|| {
+                let total_visits: u64 = moves.clone().map(|x| x.visits()).sum();
+                moves
+                    .clone()
+                    .map(|x| {
+                        if total_visits == 0 {
+                            0.0
+                        } else {
+                            x.visits() as f64 / total_visits as f64
+                        }
+                    })
+                    .collect()
+            });
+        moves.zip(probabilities).collect()
+    }
+
+    /// The evaluation of the root's state, interpreted for `player`.
+    pub(crate) fn root_reward(&self, player: &Player<Spec>) -> i64 {
+        self.eval
+            .interpret_evaluation_for_player(&self.root_node.evaln, player)
+    }
+
     pub fn root_state(&self) -> &Spec::State {
         &self.root_state
     }
-    pub fn root_node(&self) -> NodeHandle<Spec> {
+    pub(crate) fn root_search_node(&self) -> &SearchNode<Spec> {
+        &self.root_node
+    }
+
+    pub fn root_node(&self) -> NodeHandle<'_, Spec> {
         NodeHandle {
             node: &self.root_node,
         }
@@ -505,14 +904,28 @@ impl<'a, Spec: MCTS> NodeHandle<'a, Spec> {
     pub fn data(&self) -> &'a Spec::NodeData {
         &self.node.data
     }
-    pub fn moves(&self) -> Moves<Spec> {
+    pub fn moves(&self) -> Moves<'a, Spec> {
         Moves {
             iter: self.node.moves.iter(),
         }
     }
+    /// The position of `mov` among the node's moves, or `None` if it belongs to
+    /// another node.
+    pub fn move_index(&self, mov: &MoveInfo<Spec>) -> Option<usize> {
+        let range = self.node.moves.as_ptr_range();
+        let ptr = mov as *const MoveInfo<Spec>;
+        if range.contains(&ptr) {
+            Some((ptr as usize - range.start as usize) / mem::size_of::<MoveInfo<Spec>>())
+        } else {
+            None
+        }
+    }
     pub fn into_raw(&self) -> *const () {
         self.node as *const _ as *const ()
     }
+    /// # Safety
+    ///
+    /// `ptr` must have been returned by `into_raw` on a node that is still alive.
     pub unsafe fn from_raw(ptr: *const ()) -> Self {
         NodeHandle {
             node: &*(ptr as *const SearchNode<Spec>),
@@ -532,10 +945,31 @@ impl<'a, Spec: 'a + MCTS> Iterator for Moves<'a, Spec> {
     }
 }
 
+/// The moves of a playout from one node on, and the players who chose them, for
+/// `TreePolicy::on_backpropagation`. Only the moves made in the tree are included, not
+/// any the evaluator may have simulated.
+pub struct PlayoutMoves<'a, Spec: 'a + MCTS> {
+    moves: &'a [&'a MoveInfo<Spec>],
+    players: &'a [Player<Spec>],
+}
+
+impl<'a, Spec: MCTS> PlayoutMoves<'a, Spec> {
+    /// The move chosen at the node.
+    pub fn choice(&self) -> &'a MoveInfo<Spec> {
+        self.moves[0]
+    }
+
+    /// Every move from the node's choice on, with the player who chose it.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a MoveInfo<Spec>, &'a Player<Spec>)> {
+        self.moves.iter().cloned().zip(self.players.iter())
+    }
+}
+
 pub struct SearchHandle<'a, Spec: 'a + MCTS> {
     node: &'a SearchNode<Spec>,
     tld: &'a mut ThreadData<Spec>,
     manager: &'a Spec,
+    is_root: bool,
 }
 
 impl<'a, Spec: MCTS> SearchHandle<'a, Spec> {
@@ -550,6 +984,34 @@ impl<'a, Spec: MCTS> SearchHandle<'a, Spec> {
     pub fn mcts(&self) -> &'a Spec {
         self.manager
     }
+
+    /// Whether the node is the root of the search tree.
+    pub fn is_root(&self) -> bool {
+        self.is_root
+    }
+}
+
+#[cfg(test)]
+impl<Spec: MCTS> MoveInfo<Spec> {
+    /// A move whose finished playouts got `rewards`, for tests that need a root with
+    /// particular statistics.
+    pub(crate) fn with_rewards(
+        mov: Move<Spec>,
+        move_evaluation: MoveEvaluation<Spec>,
+        rewards: &[i64],
+    ) -> Self {
+        let info = Self::new(mov, move_evaluation);
+        let sum: i64 = rewards.iter().sum();
+        let sum_squares: f64 = rewards.iter().map(|&x| (x as f64) * (x as f64)).sum();
+        info.stats.visits.store(rewards.len(), Ordering::Relaxed);
+        info.stats
+            .sum_evaluations
+            .store(sum as FakeI64, Ordering::Relaxed);
+        info.stats
+            .sum_squared_evaluations
+            .store(sum_squares.to_bits(), Ordering::Relaxed);
+        info
+    }
 }
 
 impl NodeStats {
@@ -557,6 +1019,7 @@ impl NodeStats {
         NodeStats {
             sum_evaluations: AtomicI64::new(0),
             visits: AtomicUsize::new(0),
+            sum_squared_evaluations: AtomicU64::new(0f64.to_bits()),
         }
     }
 
@@ -574,6 +1037,22 @@ impl NodeStats {
         let delta = evaln + manager.virtual_loss();
         self.sum_evaluations
             .fetch_add(delta as FakeI64, Ordering::Relaxed);
+        let square = (evaln as f64) * (evaln as f64);
+        let _ =
+            self.sum_squared_evaluations
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |x| {
+                    Some((f64::from_bits(x) + square).to_bits())
+                });
+    }
+
+    fn sum_squared_evaluations(&self) -> f64 {
+        f64::from_bits(self.sum_squared_evaluations.load(Ordering::Relaxed))
+    }
+
+    fn revert<Spec: MCTS>(&self, manager: &Spec) {
+        self.sum_evaluations
+            .fetch_add(manager.virtual_loss() as FakeI64, Ordering::Relaxed);
+        self.visits.fetch_sub(1, Ordering::Relaxed);
     }
 
     fn replace(&self, other: &NodeStats) {
@@ -583,6 +1062,10 @@ impl NodeStats {
             other.sum_evaluations.load(Ordering::Relaxed),
             Ordering::Relaxed,
         );
+        self.sum_squared_evaluations.store(
+            other.sum_squared_evaluations.load(Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
     }
 }
 
@@ -603,3 +1086,419 @@ impl<'a> Drop for IncreaseSentinel<'a> {
         self.x.fetch_sub(1, Ordering::Relaxed);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_game::*;
+
+    fn reachable_nodes<Spec: MCTS>(tree: &SearchTree<Spec>) -> Vec<&SearchNode<Spec>> {
+        let root: &SearchNode<Spec> = &tree.root_node;
+        let mut seen = HashSet::new();
+        seen.insert(root as *const SearchNode<Spec>);
+        let mut nodes = vec![root];
+        let mut i = 0;
+        while i < nodes.len() {
+            for info in &nodes[i].moves {
+                let child = info.child.load(Ordering::SeqCst) as *const SearchNode<Spec>;
+                if !child.is_null() && seen.insert(child) {
+                    nodes.push(unsafe { &*child });
+                }
+            }
+            i += 1;
+        }
+        nodes
+    }
+
+    // Checks that the counters match the nodes reachable from the root, that each of
+    // those nodes except the root is owned exactly once, and that the table only
+    // finds reachable nodes.
+    fn check_tree(tree: &SearchTree<AddMCTS>) {
+        let nodes = reachable_nodes(tree);
+        assert_eq!(tree.num_nodes(), nodes.len());
+        let memory_usage: usize = nodes.iter().map(|x| node_memory_usage(x)).sum();
+        assert_eq!(tree.memory_usage(), memory_usage);
+
+        for &node in &nodes {
+            let owners = nodes
+                .iter()
+                .flat_map(|x| &x.moves)
+                .filter(|x| x.owned.load(Ordering::SeqCst))
+                .filter(|x| std::ptr::eq(x.child.load(Ordering::SeqCst), node))
+                .count();
+            let expected = if std::ptr::eq(node, tree.root_node.as_ref()) {
+                0
+            } else {
+                1
+            };
+            assert_eq!(owners, expected, "{:?} has {} owners", node.state, owners);
+        }
+
+        for state in (0..END + 2).map(AddGame) {
+            let node = tree.get_node(&state);
+            if state.0 < tree.root_state().0 {
+                assert!(node.is_none(), "{:?} wasn't freed", state);
+            } else if let Some(node) = node {
+                assert!(nodes.iter().any(|&x| std::ptr::eq(x, node)));
+            }
+        }
+    }
+
+    #[test]
+    fn advance_frees_unreachable_nodes() {
+        let mut manager = manager();
+        manager.playout_n(2000).unwrap();
+        check_tree(manager.tree());
+        assert!(manager.tree().num_nodes() > END as usize);
+
+        for mov in [Add::Two, Add::One, Add::Two, Add::Two, Add::One] {
+            let num_nodes = manager.tree().num_nodes();
+            manager.advance(&mov);
+            check_tree(manager.tree());
+            assert!(manager.tree().num_nodes() < num_nodes);
+            manager.playout_n(500).unwrap();
+            check_tree(manager.tree());
+        }
+    }
+
+    #[test]
+    fn restricted_root_never_visits_excluded_moves() {
+        for num_threads in [1, 4] {
+            let mut manager = manager();
+            manager.restrict_root_moves(&[Add::One]);
+            manager.playout_n_parallel(1000, num_threads).unwrap();
+            let tree = manager.tree();
+            for info in &tree.root_node.moves {
+                if info.mov == Add::One {
+                    assert!(info.visits() >= 1000);
+                } else {
+                    assert_eq!(info.visits(), 0);
+                    assert!(info.child.load(Ordering::SeqCst).is_null());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn advance_to_node_owned_by_sibling_subtree() {
+        let mut manager = manager();
+        // only the subtree of `One` is searched, and it creates the node of 2 below 1
+        manager.restrict_root_moves(&[Add::One]);
+        manager.playout_n(500).unwrap();
+        manager.clear_root_restriction();
+        let tree = manager.tree();
+        let two = tree
+            .root_node
+            .moves
+            .iter()
+            .find(|x| x.mov == Add::Two)
+            .unwrap();
+        assert!(two.child.load(Ordering::SeqCst).is_null());
+        let new_root = tree.get_node(&AddGame(2)).unwrap() as *const SearchNode<AddMCTS>;
+        let visits = unsafe { (*new_root).stats.visits.load(Ordering::SeqCst) };
+        assert!(visits > 0);
+
+        manager.advance(&Add::Two);
+        let tree = manager.tree();
+        assert!(std::ptr::eq(tree.root_node.as_ref(), new_root));
+        assert_eq!(tree.root_node.stats.visits.load(Ordering::SeqCst), visits);
+        check_tree(tree);
+
+        manager.playout_n(500).unwrap();
+        manager.advance(&Add::One);
+        check_tree(manager.tree());
+    }
+
+    #[test]
+    fn advance_to_node_owned_below_orphan() {
+        let mut manager = manager();
+        manager.restrict_root_moves(&[Add::Two]);
+        manager.playout_n(1).unwrap();
+        manager.clear_root_restriction();
+
+        // Recreate what a lost race to the table leaves behind: an orphaned node of 1,
+        // which another thread expanded so that it owns the node of 2.
+        let tree = &mut manager.search_tree;
+        let two = tree
+            .root_node
+            .moves
+            .iter()
+            .find(|x| x.mov == Add::Two)
+            .unwrap();
+        let new_root = two.child.load(Ordering::SeqCst);
+        assert!(!new_root.is_null());
+        two.owned.store(false, Ordering::SeqCst);
+        let orphan = create_node(&tree.eval, &tree.tree_policy, &AddGame(1), None);
+        let one = orphan.moves.iter().find(|x| x.mov == Add::One).unwrap();
+        one.child.store(new_root, Ordering::SeqCst);
+        one.owned.store(true, Ordering::SeqCst);
+        tree.memory_usage
+            .fetch_add(node_memory_usage(&orphan), Ordering::SeqCst);
+        tree.orphaned.get_mut().unwrap().push(Box::new(orphan));
+
+        manager.advance(&Add::Two);
+        assert!(std::ptr::eq(manager.tree().root_node.as_ref(), new_root));
+        assert!(manager.tree().orphaned.lock().unwrap().is_empty());
+        check_tree(manager.tree());
+        manager.playout_n(100).unwrap();
+        check_tree(manager.tree());
+    }
+
+    #[test]
+    fn reset_discards_every_node() {
+        let mut manager = manager();
+        manager.playout_n(2000).unwrap();
+        manager.advance(&Add::One);
+        manager.playout_n(500).unwrap();
+
+        let mut manager = manager.reset(AddGame(3));
+        let tree = manager.tree();
+        assert_eq!(tree.root_state(), &AddGame(3));
+        assert_eq!(tree.num_nodes(), 1);
+        assert_eq!(tree.memory_usage(), node_memory_usage(&tree.root_node));
+        assert_eq!(tree.root_node.stats.visits.load(Ordering::SeqCst), 0);
+        for state in (0..END + 2).map(AddGame) {
+            assert_eq!(tree.get_node(&state).is_some(), state == AddGame(3));
+        }
+        check_tree(tree);
+
+        manager.playout_n(500).unwrap();
+        check_tree(manager.tree());
+    }
+
+    // Implements only what `TranspositionTable` requires.
+    struct InsertOnlyTable(ApproxTable<InsertOnlyMCTS>);
+
+    unsafe impl TranspositionTable<InsertOnlyMCTS> for InsertOnlyTable {
+        fn insert<'a>(
+            &'a self,
+            key: &AddGame,
+            value: &'a SearchNode<InsertOnlyMCTS>,
+        ) -> Option<&'a SearchNode<InsertOnlyMCTS>> {
+            self.0.insert(key, value)
+        }
+
+        fn lookup<'a>(&'a self, key: &AddGame) -> Option<&'a SearchNode<InsertOnlyMCTS>> {
+            self.0.lookup(key)
+        }
+    }
+
+    #[derive(Default, Debug)]
+    struct InsertOnlyMCTS;
+
+    impl MCTS for InsertOnlyMCTS {
+        type State = AddGame;
+        type Eval = AddEvaluator;
+        type NodeData = ();
+        type ExtraThreadData = ();
+        type TreePolicy = UCTPolicy;
+        type TranspositionTable = InsertOnlyTable;
+    }
+
+    #[test]
+    fn table_without_remove_keeps_old_nodes() {
+        let mut manager = MCTSManager::new(
+            AddGame(0),
+            InsertOnlyMCTS,
+            AddEvaluator::default(),
+            UCTPolicy::new(1.0),
+            InsertOnlyTable(ApproxTable::new()),
+        );
+        manager.playout_n(2000).unwrap();
+        for mov in [Add::Two, Add::One, Add::Two] {
+            let num_nodes = manager.tree().num_nodes();
+            manager.advance(&mov);
+            assert_eq!(manager.tree().num_nodes(), num_nodes);
+            // the table still finds states before the root, and their nodes are alive
+            let node = manager.tree().get_node(&AddGame(1)).unwrap();
+            assert_eq!(node.state, AddGame(1));
+            manager.playout_n(500).unwrap();
+        }
+
+        let num_nodes = manager.tree().num_nodes();
+        let manager = manager.reset(AddGame(0));
+        assert_eq!(manager.tree().num_nodes(), num_nodes + 1);
+        let node = manager.tree().get_node(&AddGame(1)).unwrap();
+        assert_eq!(node.state, AddGame(1));
+    }
+
+    #[test]
+    fn advance_after_parallel_search() {
+        let mut manager = manager();
+        for mov in [Add::One, Add::Two, Add::Two, Add::One] {
+            manager.playout_n_parallel(2000, 4).unwrap();
+            manager.advance(&mov);
+            check_tree(manager.tree());
+        }
+    }
+
+    // Counts from 0 to 2 and starts over, so that playouts run in circles.
+    #[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    struct CycleGame(u8);
+
+    impl GameState for CycleGame {
+        type Move = ();
+        type Player = ();
+        type MoveList = Vec<()>;
+
+        fn current_player(&self) {}
+
+        fn available_moves(&self) -> Vec<()> {
+            vec![()]
+        }
+
+        fn make_move(&mut self, _: &()) {
+            self.0 = (self.0 + 1) % 3;
+        }
+    }
+
+    #[derive(Default)]
+    struct CycleEvaluator;
+
+    impl<Spec> Evaluator<Spec> for CycleEvaluator
+    where
+        Spec: MCTS<State = CycleGame, Eval = Self, TreePolicy = UCTPolicy>,
+    {
+        type StateEvaluation = i64;
+
+        fn evaluate_new_state(
+            &self,
+            _: &CycleGame,
+            moves: &Vec<()>,
+            _: Option<SearchHandle<Spec>>,
+        ) -> (Vec<()>, i64) {
+            (vec![(); moves.len()], 0)
+        }
+
+        fn interpret_evaluation_for_player(&self, evaln: &i64, _: &()) -> i64 {
+            *evaln
+        }
+
+        fn evaluate_existing_state(
+            &self,
+            _: &CycleGame,
+            evaln: &i64,
+            _: SearchHandle<Spec>,
+        ) -> i64 {
+            *evaln
+        }
+    }
+
+    macro_rules! cycle_spec {
+        ($spec:ident, $table:ty, $node_limit:expr, $max_playout_length:expr) => {
+            #[derive(Default, Debug)]
+            struct $spec;
+
+            impl MCTS for $spec {
+                type State = CycleGame;
+                type Eval = CycleEvaluator;
+                type NodeData = ();
+                type ExtraThreadData = ();
+                type TreePolicy = UCTPolicy;
+                type TranspositionTable = $table;
+
+                fn node_limit(&self) -> usize {
+                    $node_limit
+                }
+
+                fn max_playout_length(&self) -> usize {
+                    $max_playout_length
+                }
+            }
+        };
+    }
+
+    // with a table, playouts come back to the nodes they started from
+    cycle_spec!(CycleMCTS, ApproxTable<Self>, usize::MAX, 1_000_000);
+    // without one, they only get longer
+    cycle_spec!(LongPlayoutMCTS, (), usize::MAX, 5);
+    cycle_spec!(SmallTreeMCTS, (), 10, 1_000_000);
+
+    // Runs playouts until one fails, and checks that the failed playout left the
+    // statistics unchanged.
+    fn first_error<Spec>(
+        spec: Spec,
+        table: Spec::TranspositionTable,
+        state: Spec::State,
+    ) -> SearchError
+    where
+        Spec: MCTS<TreePolicy = UCTPolicy>,
+        Spec::Eval: Default,
+        ThreadData<Spec>: Default,
+    {
+        let tree = SearchTree::new(
+            state,
+            spec,
+            UCTPolicy::new(1.0),
+            Spec::Eval::default(),
+            table,
+        );
+        let mut tld = ThreadData::default();
+        for _ in 0..100 {
+            let visits: u64 = tree.root_moves().map(|x| x.visits()).sum();
+            if let Err(e) = tree.playout(&mut tld) {
+                assert_eq!(tree.root_moves().map(|x| x.visits()).sum::<u64>(), visits);
+                return e;
+            }
+        }
+        panic!("no playout failed");
+    }
+
+    #[test]
+    fn node_limit_reached() {
+        let error = first_error(SmallTreeMCTS, (), CycleGame(0));
+        assert_eq!(error, SearchError::NodeLimitReached);
+    }
+
+    #[test]
+    fn playout_too_long() {
+        let error = first_error(LongPlayoutMCTS, (), CycleGame(0));
+        assert_eq!(error, SearchError::PlayoutTooLong);
+    }
+
+    #[test]
+    fn cycle_detected() {
+        let error = first_error(CycleMCTS, ApproxTable::new(), CycleGame(0));
+        assert_eq!(error, SearchError::CycleDetected);
+    }
+
+    // Finds every state under the node of the first state of the same parity.
+    struct ParityTable(ApproxTable<ParityMCTS>);
+
+    unsafe impl TranspositionTable<ParityMCTS> for ParityTable {
+        fn insert<'a>(
+            &'a self,
+            key: &AddGame,
+            value: &'a SearchNode<ParityMCTS>,
+        ) -> Option<&'a SearchNode<ParityMCTS>> {
+            self.0.insert(&AddGame(key.0 % 2), value)
+        }
+
+        fn lookup<'a>(&'a self, key: &AddGame) -> Option<&'a SearchNode<ParityMCTS>> {
+            self.0.lookup(&AddGame(key.0 % 2))
+        }
+    }
+
+    #[derive(Default, Debug)]
+    struct ParityMCTS;
+
+    impl MCTS for ParityMCTS {
+        type State = AddGame;
+        type Eval = AddEvaluator;
+        type NodeData = ();
+        type ExtraThreadData = ();
+        type TreePolicy = UCTPolicy;
+        type TranspositionTable = ParityTable;
+
+        fn cycle_behaviour(&self) -> CycleBehaviour<Self> {
+            CycleBehaviour::Ignore
+        }
+    }
+
+    #[test]
+    fn state_mismatch() {
+        let table = ParityTable(ApproxTable::new());
+        let error = first_error(ParityMCTS, table, AddGame(0));
+        assert_eq!(error, SearchError::StateMismatch);
+    }
+}
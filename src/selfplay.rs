@@ -0,0 +1,318 @@
+//! Plays whole games with an `MCTSManager` against itself, and records the positions
+//! as training data.
+//!
+//! Every position records the root visit distribution and value. When the game ends,
+//! each record is labelled with the outcome for the player to move in it, according to
+//! `Evaluator::interpret_evaluation_for_player` on the final position.
+
+use super::*;
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io;
+use std::io::{BufRead, Write};
+use std::str::FromStr;
+
+/// How the move to play is chosen after each search.
+#[derive(Clone, Debug, PartialEq)]
+pub enum MoveChoice {
+    /// Sample in proportion to `visits^(1 / temperature)`.
+    Temperature(TemperatureSchedule),
+    /// Always play the best move according to a `MoveSelection`.
+    Best(MoveSelection),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelfPlayConfig {
+    /// The search run before each move.
+    pub limits: SearchLimits,
+    pub num_threads: usize,
+    pub move_choice: MoveChoice,
+    /// Games that reach this many moves are stopped, and labelled with the evaluation
+    /// of the last position.
+    pub max_moves: Option<usize>,
+}
+
+impl SelfPlayConfig {
+    pub fn new(limits: SearchLimits, move_choice: MoveChoice) -> Self {
+        Self {
+            limits,
+            num_threads: 1,
+            move_choice,
+            max_moves: None,
+        }
+    }
+}
+
+/// One position of a self-play game.
+#[derive(Debug)]
+pub struct SelfPlayRecord<Spec: MCTS> {
+    pub state: Spec::State,
+    /// The number of moves played before `state`.
+    pub move_number: usize,
+    /// Every root move and its visit count.
+    pub visits: Vec<(Move<Spec>, u64)>,
+    /// The mean reward of the root's playouts, for the player to move.
+    pub root_value: f64,
+    /// The move that was played.
+    pub played: Move<Spec>,
+    /// The outcome of the game for the player to move.
+    pub outcome: i64,
+}
+
+/// Plays one game from the manager's current root until a position with no moves (or
+/// `config.max_moves`), and returns its records.
+///
+/// Panics if a search doesn't visit any move, for example because `config.limits`
+/// allows no playouts.
+pub fn play_game<Spec>(
+    manager: &mut MCTSManager<Spec>,
+    config: &SelfPlayConfig,
+) -> Result<Vec<SelfPlayRecord<Spec>>, SearchError>
+where
+    Spec: MCTS + 'static,
+    ThreadData<Spec>: Default,
+{
+    let mut positions = Vec::new();
+    loop {
+        let tree = manager.tree();
+        if tree.root_search_node().moves.is_empty() {
+            break;
+        }
+        if config.max_moves.is_some_and(|x| positions.len() >= x) {
+            break;
+        }
+        manager.search(config.limits, config.num_threads)?;
+
+        let tree = manager.tree();
+        let state = tree.root_state().clone();
+        let player = state.current_player();
+        let moves = &tree.root_search_node().moves;
+        let visits: Vec<_> = moves
+            .iter()
+            .map(|x| (x.get_move().clone(), x.visits()))
+            .collect();
+        let total_visits: u64 = moves.iter().map(|x| x.visits()).sum();
+        let total_rewards: i64 = moves.iter().map(|x| x.sum_rewards()).sum();
+        let root_value = if total_visits == 0 {
+            0.0
+        } else {
+            total_rewards as f64 / total_visits as f64
+        };
+
+        let played = match config.move_choice {
+            MoveChoice::Temperature(ref schedule) => manager.move_by_temperature(schedule),
+            MoveChoice::Best(selection) => manager.best_move(selection).inspect(|mov| {
+                manager.advance(mov);
+            }),
+        }
+        .expect("the search didn't visit any move");
+
+        let record = SelfPlayRecord {
+            state,
+            move_number: positions.len(),
+            visits,
+            root_value,
+            played,
+            outcome: 0,
+        };
+        positions.push((record, player));
+    }
+
+    let tree = manager.tree();
+    Ok(positions
+        .into_iter()
+        .map(|(mut record, player)| {
+            record.outcome = tree.root_reward(&player);
+            record
+        })
+        .collect())
+}
+
+/// An iterator over the records of self-play games. Each game starts from a state
+/// returned by `new_game`. Ends at the first game that records no positions, i.e. one
+/// that starts from a finished state or has `max_moves` set to zero.
+pub struct SelfPlay<Spec: MCTS, F> {
+    manager: Option<MCTSManager<Spec>>,
+    config: SelfPlayConfig,
+    new_game: F,
+    records: VecDeque<SelfPlayRecord<Spec>>,
+    games_played: usize,
+}
+
+impl<Spec, F> SelfPlay<Spec, F>
+where
+    Spec: MCTS + 'static,
+    ThreadData<Spec>: Default,
+    F: FnMut() -> Spec::State,
+{
+    pub fn new(manager: MCTSManager<Spec>, config: SelfPlayConfig, new_game: F) -> Self {
+        Self {
+            manager: Some(manager),
+            config,
+            new_game,
+            records: VecDeque::new(),
+            games_played: 0,
+        }
+    }
+
+    pub fn games_played(&self) -> usize {
+        self.games_played
+    }
+
+    pub fn into_manager(self) -> MCTSManager<Spec> {
+        self.manager.unwrap()
+    }
+}
+
+impl<Spec, F> Iterator for SelfPlay<Spec, F>
+where
+    Spec: MCTS + 'static,
+    ThreadData<Spec>: Default,
+    F: FnMut() -> Spec::State,
+{
+    type Item = Result<SelfPlayRecord<Spec>, SearchError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.records.is_empty() {
+            let state = (self.new_game)();
+            let mut manager = self.manager.take().unwrap().reset(state);
+            let result = play_game(&mut manager, &self.config);
+            self.manager = Some(manager);
+            self.games_played += 1;
+            match result {
+                Ok(records) => self.records.extend(records),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        self.records.pop_front().map(Ok)
+    }
+}
+
+/// Writes `records` as text, one record per line. The fields are separated by tabs:
+/// the state, the move number, the move played, the root value, the outcome, and then
+/// each root move followed by its visit count. The states and moves are written with
+/// `Display`, so their text must not contain tabs or line breaks.
+pub fn write_records<'a, Spec, W, I>(writer: &mut W, records: I) -> io::Result<()>
+where
+    Spec: MCTS + 'a,
+    Spec::State: Display,
+    Move<Spec>: Display,
+    W: Write,
+    I: IntoIterator<Item = &'a SelfPlayRecord<Spec>>,
+{
+    for record in records {
+        let mut fields = vec![
+            checked_field(&record.state)?,
+            record.move_number.to_string(),
+            checked_field(&record.played)?,
+            record.root_value.to_string(),
+            record.outcome.to_string(),
+        ];
+        for (mov, visits) in &record.visits {
+            fields.push(checked_field(mov)?);
+            fields.push(visits.to_string());
+        }
+        writeln!(writer, "{}", fields.join("\t"))?;
+    }
+    Ok(())
+}
+
+/// Reads records written by `write_records`.
+pub fn read_records<Spec, R>(reader: R) -> io::Result<Vec<SelfPlayRecord<Spec>>>
+where
+    Spec: MCTS,
+    Spec::State: FromStr,
+    Move<Spec>: FromStr,
+    R: BufRead,
+{
+    let mut records = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<&str> = line.split('\t').collect();
+        // `usize::is_multiple_of` needs Rust 1.87
+        #[allow(clippy::manual_is_multiple_of)]
+        let even = fields.len() % 2 == 0;
+        if fields.len() < 5 || even {
+            return Err(invalid_data(format!(
+                "expected an odd number of fields, at least 5, but found {}",
+                fields.len()
+            )));
+        }
+        let mut visits = Vec::new();
+        for pair in fields[5..].chunks(2) {
+            visits.push((parse_field(pair[0])?, parse_field(pair[1])?));
+        }
+        records.push(SelfPlayRecord {
+            state: parse_field(fields[0])?,
+            move_number: parse_field(fields[1])?,
+            played: parse_field(fields[2])?,
+            root_value: parse_field(fields[3])?,
+            outcome: parse_field(fields[4])?,
+            visits,
+        });
+    }
+    Ok(records)
+}
+
+fn checked_field<T: Display>(x: &T) -> io::Result<String> {
+    let field = x.to_string();
+    if field.contains(['\t', '\n', '\r']) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{:?} contains a tab or line break", field),
+        ));
+    }
+    Ok(field)
+}
+
+fn parse_field<T: FromStr>(field: &str) -> io::Result<T> {
+    field
+        .parse()
+        .map_err(|_| invalid_data(format!("couldn't parse {:?}", field)))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_game::*;
+
+    fn config(max_moves: Option<usize>) -> SelfPlayConfig {
+        let mut config = SelfPlayConfig::new(
+            SearchLimits::new().playouts(100),
+            MoveChoice::Best(MoveSelection::MaxVisits),
+        );
+        config.max_moves = max_moves;
+        config
+    }
+
+    #[test]
+    fn records_every_move() {
+        let mut manager = manager();
+        let records = play_game(&mut manager, &config(None)).unwrap();
+        let mut state = AddGame(0);
+        for (i, record) in records.iter().enumerate() {
+            assert_eq!(record.move_number, i);
+            assert_eq!(record.state, state);
+            state.make_move(&record.played);
+        }
+        assert!(state.0 >= END);
+    }
+
+    #[test]
+    fn ends_on_a_game_without_moves() {
+        let mut games = SelfPlay::new(manager(), config(None), || AddGame(END));
+        assert!(games.next().is_none());
+        assert_eq!(games.games_played(), 1);
+
+        let mut games = SelfPlay::new(manager(), config(Some(0)), || AddGame(0));
+        assert!(games.next().is_none());
+        assert_eq!(games.games_played(), 1);
+    }
+}
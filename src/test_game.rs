@@ -0,0 +1,168 @@
+//! A small game for the unit tests.
+
+use super::*;
+use std::sync::atomic::AtomicBool;
+use transposition_table::ApproxTable;
+
+/// A number that starts at zero. Each move adds one or two, and the game ends once the
+/// number reaches `END`. Different move orders reach the same numbers, so the
+/// transposition table merges their nodes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AddGame(pub i64);
+
+pub const END: i64 = 20;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Add {
+    One,
+    Two,
+}
+
+impl GameState for AddGame {
+    type Move = Add;
+    type Player = ();
+    type MoveList = Vec<Add>;
+
+    fn current_player(&self) {}
+
+    fn available_moves(&self) -> Vec<Add> {
+        if self.0 >= END {
+            vec![]
+        } else {
+            vec![Add::One, Add::Two]
+        }
+    }
+
+    fn make_move(&mut self, mov: &Add) {
+        match *mov {
+            Add::One => self.0 += 1,
+            Add::Two => self.0 += 2,
+        }
+    }
+}
+
+/// Evaluates a state as its number. Panics once on the next new state after `panic` is
+/// set.
+#[derive(Default)]
+pub struct AddEvaluator {
+    pub panic: Arc<AtomicBool>,
+}
+
+impl<Spec> Evaluator<Spec> for AddEvaluator
+where
+    Spec: MCTS<State = AddGame, Eval = Self, TreePolicy = UCTPolicy>,
+{
+    type StateEvaluation = i64;
+
+    fn evaluate_new_state(
+        &self,
+        state: &AddGame,
+        moves: &Vec<Add>,
+        _: Option<SearchHandle<Spec>>,
+    ) -> (Vec<()>, i64) {
+        if self.panic.swap(false, Ordering::SeqCst) {
+            panic!("evaluator panicked on {:?}", state);
+        }
+        (vec![(); moves.len()], state.0)
+    }
+
+    fn interpret_evaluation_for_player(&self, evaln: &i64, _: &()) -> i64 {
+        *evaln
+    }
+
+    fn evaluate_existing_state(&self, _: &AddGame, evaln: &i64, _: SearchHandle<Spec>) -> i64 {
+        *evaln
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct AddMCTS;
+
+impl MCTS for AddMCTS {
+    type State = AddGame;
+    type Eval = AddEvaluator;
+    type NodeData = ();
+    type ExtraThreadData = ();
+    type TreePolicy = UCTPolicy;
+    type TranspositionTable = ApproxTable<Self>;
+}
+
+pub fn manager_with_evaluator(evaluator: AddEvaluator) -> MCTSManager<AddMCTS> {
+    let mut manager = MCTSManager::new(
+        AddGame(0),
+        AddMCTS,
+        evaluator,
+        UCTPolicy::new(1.0),
+        ApproxTable::new(),
+    );
+    manager.seed(1);
+    manager
+}
+
+pub fn manager() -> MCTSManager<AddMCTS> {
+    manager_with_evaluator(AddEvaluator::default())
+}
+
+/// The prior `PriorEvaluator` gives `One`. `Two` gets the rest.
+pub const ONE_PRIOR: f64 = 0.75;
+
+/// Evaluates a state as its number, like `AddEvaluator`, and gives the moves the priors
+/// `ONE_PRIOR` and `1 - ONE_PRIOR`.
+#[derive(Default)]
+pub struct PriorEvaluator;
+
+impl<Spec> Evaluator<Spec> for PriorEvaluator
+where
+    Spec: MCTS<State = AddGame, Eval = Self>,
+    Spec::TreePolicy: TreePolicy<Spec, MoveEvaluation = f64>,
+{
+    type StateEvaluation = i64;
+
+    fn evaluate_new_state(
+        &self,
+        state: &AddGame,
+        moves: &Vec<Add>,
+        _: Option<SearchHandle<Spec>>,
+    ) -> (Vec<f64>, i64) {
+        let priors = moves
+            .iter()
+            .map(|x| match *x {
+                Add::One => ONE_PRIOR,
+                Add::Two => 1.0 - ONE_PRIOR,
+            })
+            .collect();
+        (priors, state.0)
+    }
+
+    fn interpret_evaluation_for_player(&self, evaln: &i64, _: &()) -> i64 {
+        *evaln
+    }
+
+    fn evaluate_existing_state(&self, _: &AddGame, evaln: &i64, _: SearchHandle<Spec>) -> i64 {
+        *evaln
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct AlphaGoMCTS;
+
+impl MCTS for AlphaGoMCTS {
+    type State = AddGame;
+    type Eval = PriorEvaluator;
+    type NodeData = ();
+    type ExtraThreadData = ();
+    type TreePolicy = AlphaGoPolicy;
+    type TranspositionTable = ApproxTable<Self>;
+}
+
+pub fn alphago_manager(policy: AlphaGoPolicy) -> MCTSManager<AlphaGoMCTS> {
+    let mut manager = MCTSManager::new(
+        AddGame(0),
+        AlphaGoMCTS,
+        PriorEvaluator,
+        policy,
+        ApproxTable::new(),
+    );
+    manager.seed(1);
+    manager
+}
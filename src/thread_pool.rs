@@ -0,0 +1,174 @@
+use super::*;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::mpsc::{channel, Sender};
+
+type Job<Spec> = Box<dyn FnOnce(&mut WorkerData<Spec>) + Send>;
+
+/// Search threads that stay alive between searches, so that each thread keeps its
+/// `ThreadData`.
+pub(crate) struct ThreadPool<Spec: MCTS> {
+    workers: Vec<Worker<Spec>>,
+}
+
+struct Worker<Spec: MCTS> {
+    jobs: Sender<Job<Spec>>,
+    thread: JoinHandle<()>,
+}
+
+/// The state a worker keeps between jobs.
+pub(crate) struct WorkerData<Spec: MCTS> {
+    seed: Option<u64>,
+    // created by the first job, since creating it needs the search tree
+    tld: Option<ThreadData<Spec>>,
+}
+
+impl<Spec: MCTS> WorkerData<Spec>
+where
+    ThreadData<Spec>: Default,
+{
+    pub(crate) fn thread_data(&mut self, search_tree: &SearchTree<Spec>) -> &mut ThreadData<Spec> {
+        if self.tld.is_none() {
+            self.tld = Some(new_thread_data(search_tree, self.seed));
+        }
+        self.tld.as_mut().unwrap()
+    }
+}
+
+impl<Spec: MCTS + 'static> ThreadPool<Spec> {
+    pub(crate) fn new() -> Self {
+        Self {
+            workers: Vec::new(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Starts another worker. Its thread data will be seeded with `seed`.
+    pub(crate) fn add_worker(&mut self, seed: Option<u64>) {
+        let (jobs, receiver) = channel::<Job<Spec>>();
+        let thread = thread::spawn(move || {
+            let mut data = WorkerData { seed, tld: None };
+            for job in receiver {
+                job(&mut data);
+            }
+        });
+        self.workers.push(Worker { jobs, thread });
+    }
+
+    /// Calls `f` on the first `num_threads` workers at once, and returns when every call
+    /// has returned. If `f` panics, the panic is resumed on the calling thread.
+    pub(crate) fn run<F>(&mut self, num_threads: usize, f: F)
+    where
+        F: Fn(&mut WorkerData<Spec>) + Sync,
+    {
+        assert!(num_threads <= self.workers.len());
+        let f: &(dyn Fn(&mut WorkerData<Spec>) + Sync) = &f;
+        // Every job holds a `Sender` for `done`, so the loop below only finishes once
+        // every job has either run or been dropped. Nothing can use `f` after that.
+        let f = unsafe {
+            mem::transmute::<
+                &(dyn Fn(&mut WorkerData<Spec>) + Sync),
+                &'static (dyn Fn(&mut WorkerData<Spec>) + Sync),
+            >(f)
+        };
+        let (done_sender, done) = channel();
+        for worker in &self.workers[..num_threads] {
+            let done_sender = done_sender.clone();
+            let job: Job<Spec> = Box::new(move |data| {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| f(data)));
+                let _ = done_sender.send(result);
+            });
+            // a worker only stops when the pool is dropped, but if the send fails
+            // anyway the job is simply dropped
+            let _ = worker.jobs.send(job);
+        }
+        drop(done_sender);
+
+        let mut panic = None;
+        for result in done.iter() {
+            if let Err(payload) = result {
+                panic.get_or_insert(payload);
+            }
+        }
+        if let Some(payload) = panic {
+            panic::resume_unwind(payload);
+        }
+    }
+}
+
+impl<Spec: MCTS> Drop for ThreadPool<Spec> {
+    fn drop(&mut self) {
+        for worker in self.workers.drain(..) {
+            // closing the channel ends the worker's loop
+            drop(worker.jobs);
+            let _ = worker.thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use test_game::*;
+
+    fn root_visits(manager: &MCTSManager<AddMCTS>) -> u64 {
+        manager.tree().root_moves().map(|x| x.visits()).sum()
+    }
+
+    #[test]
+    fn repeated_searches_reuse_workers() {
+        let mut manager = manager();
+        for i in 1..=10 {
+            manager.playout_n_parallel(300, 4).unwrap();
+            assert_eq!(manager.thread_pool.len(), 4);
+            assert!(root_visits(&manager) >= 300 * i);
+        }
+        manager.playout_n_parallel(300, 2).unwrap();
+        assert_eq!(manager.thread_pool.len(), 4);
+        assert!(root_visits(&manager) >= 3300);
+    }
+
+    #[test]
+    fn panic_is_resumed_on_caller() {
+        let panic = Arc::new(AtomicBool::new(false));
+        let mut manager = manager_with_evaluator(AddEvaluator {
+            panic: panic.clone(),
+        });
+        manager.playout_n_parallel(10, 4).unwrap();
+
+        panic.store(true, Ordering::SeqCst);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| manager.playout_n_parallel(1000, 4)));
+        let payload = result.expect_err("the search didn't panic");
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("evaluator panicked"), "{}", message);
+
+        panic.store(false, Ordering::SeqCst);
+        let visits = root_visits(&manager);
+        manager.playout_n_parallel(1000, 4).unwrap();
+        assert_eq!(manager.thread_pool.len(), 4);
+        assert!(root_visits(&manager) >= visits + 1000);
+        assert!(manager.best_move(MoveSelection::MaxVisits).is_some());
+    }
+
+    #[test]
+    fn panic_stops_other_workers() {
+        // none of these limits is reached by this game, so the search only stops
+        // because a worker panicked
+        for limits in [
+            SearchLimits::new().nodes(1_000_000),
+            SearchLimits::new().time(Duration::from_secs(3600)),
+        ] {
+            let panic = Arc::new(AtomicBool::new(false));
+            let mut manager = manager_with_evaluator(AddEvaluator {
+                panic: panic.clone(),
+            });
+            panic.store(true, Ordering::SeqCst);
+            let result = panic::catch_unwind(AssertUnwindSafe(|| manager.search(limits, 4)));
+            assert!(result.is_err());
+        }
+    }
+}
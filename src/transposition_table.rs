@@ -3,6 +3,10 @@ use std::{hash::Hash, marker::PhantomData};
 use super::*;
 use search_tree::*;
 
+/// # Safety
+///
+/// The search tree trusts the references returned by `insert` and `lookup`. See
+/// the documentation of `insert` for the rule implementations must follow.
 pub unsafe trait TranspositionTable<Spec: MCTS>: Sync + Sized {
     /// **If this function inserts a value, it must return `None`.** Failure to follow
     /// this rule will lead to memory safety violation.
@@ -34,6 +38,26 @@ pub unsafe trait TranspositionTable<Spec: MCTS>: Sync + Sized {
     /// If the key is present, the table *may return either* `None` or a reference
     /// to the associated value.
     fn lookup<'a>(&'a self, key: &Spec::State) -> Option<&'a SearchNode<Spec>>;
+
+    /// Removes `key` if it is associated with `value`.
+    ///
+    /// The search tree calls this before freeing a node, so afterwards the table
+    /// must never return `value` again. The default does nothing, which is only
+    /// correct together with the default `supports_remove`.
+    fn remove(&self, _key: &Spec::State, _value: &SearchNode<Spec>) {}
+
+    /// Whether `remove` is implemented. If it isn't, the search tree never frees a
+    /// node the table might still return: nodes that `SearchTree::advance` leaves
+    /// unreachable are kept until the tree is dropped.
+    fn supports_remove(&self) -> bool {
+        false
+    }
+
+    /// Removes every entry. Called when the search tree is reset.
+    ///
+    /// The search tree still calls `remove` for every node it frees afterwards, so
+    /// this is only a shortcut. The default does nothing.
+    fn clear(&mut self) {}
 }
 
 unsafe impl<Spec: MCTS<TranspositionTable = Self>> TranspositionTable<Spec> for () {
@@ -48,6 +72,11 @@ unsafe impl<Spec: MCTS<TranspositionTable = Self>> TranspositionTable<Spec> for
     fn lookup<'a>(&'a self, _: &Spec::State) -> Option<&'a SearchNode<Spec>> {
         None
     }
+
+    fn supports_remove(&self) -> bool {
+        // nothing is ever stored, so there is nothing to remove
+        true
+    }
 }
 
 pub struct LockFreeHashTable<K, V> {
@@ -64,6 +93,12 @@ impl<K, V> LockFreeHashTable<K, V> {
     }
 }
 
+impl<K, V> Default for LockFreeHashTable<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub type ApproxTable<Spec> = LockFreeHashTable<<Spec as MCTS>::State, SearchNode<Spec>>;
 
 unsafe impl<Spec> TranspositionTable<Spec> for ApproxTable<Spec>
@@ -76,18 +111,44 @@ where
         key: &Spec::State,
         value: &'a SearchNode<Spec>,
     ) -> Option<&'a SearchNode<Spec>> {
-        let value = unsafe { mem::transmute::<_, usize>(value) };
+        let value = unsafe { mem::transmute::<&SearchNode<Spec>, usize>(value) };
 
-        match self.inner.insert(key.clone(), value) {
-            Some(removed) => unsafe { Some(mem::transmute::<_, &SearchNode<Spec>>(removed.1)) },
-            None => None,
+        // `Map::insert` replaces existing values, which would break the rule above,
+        // so only insert when the key is absent.
+        let mut existing = None;
+        let insertion = self
+            .inner
+            .insert_with(key.clone(), |_, _, stored| match stored {
+                Some(stored) => {
+                    existing = Some(stored.1);
+                    lockfree::map::Preview::Discard
+                }
+                None => lockfree::map::Preview::New(value),
+            });
+
+        match insertion {
+            lockfree::map::Insertion::Created => None,
+            _ => existing.map(|x| unsafe { mem::transmute::<usize, &SearchNode<Spec>>(x) }),
         }
     }
 
     fn lookup<'a>(&'a self, key: &Spec::State) -> Option<&'a SearchNode<Spec>> {
         match self.inner.get(key) {
-            Some(value) => unsafe { Some(mem::transmute::<_, &SearchNode<Spec>>(value.1)) },
+            Some(value) => unsafe { Some(mem::transmute::<usize, &SearchNode<Spec>>(value.1)) },
             None => None,
         }
     }
+
+    fn remove(&self, key: &Spec::State, value: &SearchNode<Spec>) {
+        let value = value as *const SearchNode<Spec> as usize;
+        self.inner.remove_with(key, |x| x.1 == value);
+    }
+
+    fn supports_remove(&self) -> bool {
+        true
+    }
+
+    fn clear(&mut self) {
+        self.inner.clear();
+    }
 }
@@ -1,12 +1,15 @@
 extern crate rand;
 extern crate rand_xorshift;
 
-use self::rand::{Rng, SeedableRng};
+use self::rand::{Error, Rng, RngCore, SeedableRng};
 
 use self::rand_xorshift::XorShiftRng;
 use super::*;
+use atomics::*;
 use search_tree::*;
-use std;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::sync::OnceLock;
 
 pub trait TreePolicy<Spec: MCTS<TreePolicy = Self>>: Sync + Sized {
     type MoveEvaluation: Sync + Send;
@@ -20,6 +23,53 @@ pub trait TreePolicy<Spec: MCTS<TreePolicy = Self>>: Sync + Sized {
     where
         MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone;
     fn validate_evaluations(&self, _evalns: &[Self::MoveEvaluation]) {}
+
+    /// Called when `MCTSManager::seed` is set, on the thread local data of every search
+    /// thread. Policies that use randomness should reseed it from `seed`.
+    fn seed_thread_data(&self, _data: &mut Self::ThreadLocalData, _seed: u64) {}
+
+    /// Returns the evaluation of one of the root's moves with `noise` mixed in with
+    /// weight `epsilon`, for `MCTSManager::root_noise`. The noise of all the root's moves
+    /// sums to one. The search tree keeps `evaln` and puts it back once the root changes.
+    /// Policies whose evaluations aren't priors return `None`.
+    fn noisy_evaluation(
+        &self,
+        _evaln: &Self::MoveEvaluation,
+        _noise: f64,
+        _epsilon: f64,
+    ) -> Option<Self::MoveEvaluation> {
+        None
+    }
+
+    /// Called for each move of a finished playout, last move first, with the reward
+    /// for the player who chose it. `moves` and `handle` are those `choose_child` was
+    /// given when it chose `playout.choice()`.
+    fn on_backpropagation<'a, MoveIter>(
+        &self,
+        _moves: MoveIter,
+        _playout: PlayoutMoves<Spec>,
+        _reward: i64,
+        _handle: SearchHandle<Spec>,
+    ) where
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+        Spec: 'a,
+    {
+    }
+
+    /// The mixed strategy at a node, as the probability of each move in `moves`, for
+    /// `MCTSManager::root_strategy`. Policies that choose deterministically return
+    /// `None`, and the proportion of visits is used instead.
+    fn mixed_strategy<'a, MoveIter>(
+        &self,
+        _moves: MoveIter,
+        _node: NodeHandle<Spec>,
+    ) -> Option<Vec<f64>>
+    where
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+        Spec: 'a,
+    {
+        None
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -44,12 +94,303 @@ impl UCTPolicy {
     }
 }
 
+/// UCB1-Tuned (Auer et al., "Finite-time Analysis of the Multiarmed Bandit Problem"),
+/// which scales each move's exploration by the variance of its rewards, so that moves
+/// with near-deterministic rewards need fewer visits.
+#[derive(Clone, Debug)]
+pub struct UCB1TunedPolicy {
+    exploration_constant: f64,
+    loss: i64,
+    win: i64,
+}
+
+impl UCB1TunedPolicy {
+    /// Rewards are scaled to between 0 and 1, from `loss` to `win`, as the variance
+    /// bound of 1/4 in the paper assumes. With an exploration constant of 1 this is the
+    /// paper's formula.
+    pub fn new(exploration_constant: f64, loss: i64, win: i64) -> Self {
+        assert!(
+            exploration_constant > 0.0,
+            "exploration constant is {} (must be positive)",
+            exploration_constant
+        );
+        assert!(
+            loss < win,
+            "loss reward {} must be below win reward {}",
+            loss,
+            win
+        );
+        Self {
+            exploration_constant,
+            loss,
+            win,
+        }
+    }
+
+    pub fn exploration_constant(&self) -> f64 {
+        self.exploration_constant
+    }
+}
+
+/// Thompson sampling: every move's mean reward is sampled from its posterior, and the
+/// move with the highest sample is chosen. Threads draw different samples, so parallel
+/// searches diversify without relying on virtual loss.
+#[derive(Clone, Debug)]
+pub struct ThompsonPolicy {
+    posterior: Posterior,
+}
+
+/// The posterior distribution of a move's mean reward for `ThompsonPolicy`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Posterior {
+    /// For rewards that are either `loss` or `win`. Rewards in between, such as draws,
+    /// count as partial wins. The posterior is `Beta(1 + wins, 1 + losses)`.
+    Beta { loss: i64, win: i64 },
+    /// A normal distribution around the mean reward, with the variance of the rewards
+    /// divided by the visits. Moves with fewer than two visits use `default_variance`,
+    /// and unvisited moves are always tried first.
+    Gaussian { default_variance: f64 },
+}
+
+impl ThompsonPolicy {
+    pub fn new(posterior: Posterior) -> Self {
+        match posterior {
+            Posterior::Beta { loss, win } => {
+                assert!(
+                    loss < win,
+                    "loss reward {} must be below win reward {}",
+                    loss,
+                    win
+                )
+            }
+            Posterior::Gaussian { default_variance } => assert!(
+                default_variance >= 0.0,
+                "default variance is {} (must not be negative)",
+                default_variance
+            ),
+        }
+        Self { posterior }
+    }
+
+    pub fn posterior(&self) -> Posterior {
+        self.posterior
+    }
+
+    fn sample<Spec: MCTS, R: Rng>(&self, mov: &MoveInfo<Spec>, rng: &mut R) -> f64 {
+        let visits = mov.visits();
+        match self.posterior {
+            Posterior::Beta { loss, win } => {
+                let n = visits as f64;
+                let win_rate =
+                    ((mov.mean_reward() - loss as f64) / (win - loss) as f64).clamp(0.0, 1.0);
+                let p = sampling::beta(1.0 + win_rate * n, 1.0 + (1.0 - win_rate) * n, rng);
+                loss as f64 + p * (win - loss) as f64
+            }
+            Posterior::Gaussian { default_variance } => {
+                if visits == 0 {
+                    return f64::INFINITY;
+                }
+                let variance = if visits < 2 {
+                    default_variance
+                } else {
+                    mov.reward_variance()
+                };
+                let sd = (variance / visits as f64).sqrt();
+                mov.mean_reward() + sd * sampling::standard_normal(rng)
+            }
+        }
+    }
+}
+
+/// EXP3 (Auer et al., "The Nonstochastic Multiarmed Bandit Problem") for
+/// simultaneous-move games. At every node, each player samples an action from their
+/// own mixed strategy, so the search can converge to an equilibrium where a player
+/// doesn't see the other's move. See `JointMove`.
+///
+/// The spec's node data must be `BanditData`. `MCTSManager::root_strategy` reports the
+/// players' average strategies at the root.
+#[derive(Clone, Debug)]
+pub struct Exp3Policy {
+    gamma: f64,
+    loss: i64,
+    win: i64,
+}
+
+impl Exp3Policy {
+    /// `gamma` is the probability of exploring a uniformly random action. Rewards are
+    /// scaled to between 0 and 1, from `loss` to `win`.
+    pub fn new(gamma: f64, loss: i64, win: i64) -> Self {
+        assert!(
+            gamma > 0.0 && gamma <= 1.0,
+            "gamma is {} (must be in (0, 1])",
+            gamma
+        );
+        assert!(
+            loss < win,
+            "loss reward {} must be below win reward {}",
+            loss,
+            win
+        );
+        Self { gamma, loss, win }
+    }
+
+    pub fn gamma(&self) -> f64 {
+        self.gamma
+    }
+}
+
+/// Regret matching for simultaneous-move games, with the sampled regret updates of
+/// Lanctot et al., "Monte Carlo Tree Search in Simultaneous Move Games with
+/// Applications to Goofspiel". At every node, each player plays an action in
+/// proportion to its positive cumulative regret, mixed with uniform exploration. See
+/// `JointMove`.
+///
+/// The spec's node data must be `BanditData`. `MCTSManager::root_strategy` reports the
+/// players' average strategies at the root.
+#[derive(Clone, Debug)]
+pub struct RegretMatchingPolicy {
+    gamma: f64,
+}
+
+impl RegretMatchingPolicy {
+    /// `gamma` is the probability of exploring a uniformly random action.
+    pub fn new(gamma: f64) -> Self {
+        assert!(
+            gamma > 0.0 && gamma <= 1.0,
+            "gamma is {} (must be in (0, 1])",
+            gamma
+        );
+        Self { gamma }
+    }
+
+    pub fn gamma(&self) -> f64 {
+        self.gamma
+    }
+}
+
+/// UCT with Rapid Action Value Estimation (Gelly and Silver, "Combining Online and
+/// Offline Knowledge in UCT"). Each move's mean reward is blended with its
+/// all-moves-as-first (AMAF) value: the mean reward of the playouts through the node in
+/// which the same player made that move at any later point. AMAF values are available
+/// after few playouts but are biased, so their weight shrinks as the move is visited.
+///
+/// The spec's node data must be `RaveData`, moves must be hashable and players must be
+/// comparable. Evaluators that simulate moves past the tree should report them with
+/// `RaveThreadData::add_rollout_move`, so that they count towards the AMAF values.
+#[derive(Clone, Debug)]
+pub struct RAVEPolicy {
+    exploration_constant: f64,
+    schedule: RaveSchedule,
+}
+
+/// The weight `beta` of the AMAF value in `RAVEPolicy`, given a move's visits and AMAF
+/// visits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RaveSchedule {
+    /// `beta = sqrt(k / (3 * visits + k))`, where `k` is the number of visits at which
+    /// the AMAF value and the mean reward have equal weight.
+    Equivalence(f64),
+    /// Silver's minimum mean squared error schedule, `beta = amaf_visits / (visits +
+    /// amaf_visits + 4 * bias^2 * visits * amaf_visits)`, where `bias` is the expected
+    /// bias of AMAF values, in reward units.
+    MinimumMse(f64),
+}
+
+impl RaveSchedule {
+    fn beta(&self, visits: u64, amaf_visits: u64) -> f64 {
+        let n = visits as f64;
+        let amaf_n = amaf_visits as f64;
+        match *self {
+            RaveSchedule::Equivalence(k) => (k / (3.0 * n + k)).sqrt(),
+            RaveSchedule::MinimumMse(bias) => {
+                amaf_n / (n + amaf_n + 4.0 * bias * bias * n * amaf_n)
+            }
+        }
+    }
+}
+
+impl RAVEPolicy {
+    pub fn new(exploration_constant: f64, schedule: RaveSchedule) -> Self {
+        assert!(
+            exploration_constant >= 0.0,
+            "exploration constant is {} (must not be negative)",
+            exploration_constant
+        );
+        match schedule {
+            RaveSchedule::Equivalence(k) => {
+                assert!(k > 0.0, "equivalence parameter is {} (must be positive)", k)
+            }
+            RaveSchedule::MinimumMse(bias) => {
+                assert!(bias >= 0.0, "bias is {} (must not be negative)", bias)
+            }
+        }
+        Self {
+            exploration_constant,
+            schedule,
+        }
+    }
+
+    pub fn exploration_constant(&self) -> f64 {
+        self.exploration_constant
+    }
+
+    pub fn schedule(&self) -> RaveSchedule {
+        self.schedule
+    }
+}
+
 const RECIPROCAL_TABLE_LEN: usize = 128;
 
+/// How `AlphaGoPolicy` and `AlphaZeroPolicy` value moves that haven't been visited yet
+/// ("first play urgency"), in reward units. Lower values make the search explore fewer
+/// low-prior moves.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fpu {
+    /// The mean reward of the node's visited moves, minus the given reduction scaled by
+    /// the square root of their summed priors, as in Leela Zero.
+    ParentReduction(f64),
+    /// A fixed value. Loss-init is the reward of a lost game, see `Fpu::loss`.
+    Absolute(f64),
+}
+
+impl Fpu {
+    /// Values unvisited moves as losses, given the reward of a lost game.
+    pub fn loss(loss_reward: i64) -> Self {
+        Fpu::Absolute(loss_reward as f64)
+    }
+
+    fn value<'a, Spec, MoveIter>(&self, moves: MoveIter) -> f64
+    where
+        Spec: MCTS + 'a,
+        Spec::TreePolicy: TreePolicy<Spec, MoveEvaluation = f64>,
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>>,
+    {
+        match *self {
+            Fpu::Absolute(value) => value,
+            Fpu::ParentReduction(reduction) => {
+                let (mut visits, mut sum_rewards, mut visited_prior) = (0, 0, 0.0);
+                for mov in moves.filter(|x| x.visits() > 0) {
+                    visits += mov.visits();
+                    sum_rewards += mov.sum_rewards();
+                    visited_prior += *mov.move_evaluation();
+                }
+                let parent_value = if visits == 0 {
+                    0.0
+                } else {
+                    sum_rewards as f64 / visits as f64
+                };
+                parent_value - reduction * visited_prior.sqrt()
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct AlphaGoPolicy {
     exploration_constant: f64,
     reciprocals: Vec<f64>,
+    fpu: Fpu,
+    root_fpu: Option<Fpu>,
 }
 
 impl AlphaGoPolicy {
@@ -59,19 +400,40 @@ impl AlphaGoPolicy {
             "exploration constant is {} (must be positive)",
             exploration_constant
         );
-        let reciprocals = (0..RECIPROCAL_TABLE_LEN)
-            .map(|x| if x == 0 { 2.0 } else { 1.0 / x as f64 })
-            .collect();
+        let reciprocals = (0..RECIPROCAL_TABLE_LEN).map(|x| 1.0 / x as f64).collect();
         Self {
             exploration_constant,
             reciprocals,
+            fpu: Fpu::Absolute(0.0),
+            root_fpu: None,
         }
     }
 
+    /// Sets the value of unvisited moves. Defaults to `Fpu::Absolute(0.0)`.
+    pub fn fpu(mut self, fpu: Fpu) -> Self {
+        self.fpu = fpu;
+        self
+    }
+
+    /// Sets the value of unvisited moves at the root, if it should differ from the
+    /// rest of the tree.
+    pub fn root_fpu(mut self, fpu: Fpu) -> Self {
+        self.root_fpu = Some(fpu);
+        self
+    }
+
     pub fn exploration_constant(&self) -> f64 {
         self.exploration_constant
     }
 
+    fn fpu_at(&self, is_root: bool) -> Fpu {
+        if is_root {
+            self.root_fpu.unwrap_or(self.fpu)
+        } else {
+            self.fpu
+        }
+    }
+
     fn reciprocal(&self, x: usize) -> f64 {
         if x < RECIPROCAL_TABLE_LEN {
             unsafe { *self.reciprocals.get_unchecked(x) }
@@ -81,10 +443,88 @@ impl AlphaGoPolicy {
     }
 }
 
+/// PUCT as published for AlphaZero: a move's score is `Q + U`, where `Q` is its mean
+/// reward and `U = c(N) * prior * sqrt(N) / (1 + visits)` for `N` visits of the parent.
+/// The exploration coefficient `c(N) = ln((1 + N + c_base) / c_base) + c_init` grows
+/// slowly with `N`, so long searches keep exploring.
+#[derive(Clone, Debug)]
+pub struct AlphaZeroPolicy {
+    c_base: f64,
+    c_init: f64,
+    reward_scale: f64,
+    fpu: Fpu,
+    root_fpu: Option<Fpu>,
+}
+
+impl AlphaZeroPolicy {
+    /// AlphaZero used `c_base = 19652` and `c_init = 1.25`.
+    pub fn new(c_base: f64, c_init: f64) -> Self {
+        assert!(c_base > 0.0, "c_base is {} (must be positive)", c_base);
+        assert!(c_init >= 0.0, "c_init is {} (must not be negative)", c_init);
+        Self {
+            c_base,
+            c_init,
+            reward_scale: 1.0,
+            fpu: Fpu::Absolute(0.0),
+            root_fpu: None,
+        }
+    }
+
+    /// Rewards are divided by `reward_scale` to get `Q`. The published constants assume
+    /// `Q` is between -1 and 1, so this should be the reward of a won game.
+    pub fn reward_scale(mut self, reward_scale: f64) -> Self {
+        assert!(
+            reward_scale > 0.0,
+            "reward scale is {} (must be positive)",
+            reward_scale
+        );
+        self.reward_scale = reward_scale;
+        self
+    }
+
+    /// Sets the value of unvisited moves. Defaults to `Fpu::Absolute(0.0)`.
+    pub fn fpu(mut self, fpu: Fpu) -> Self {
+        self.fpu = fpu;
+        self
+    }
+
+    /// Sets the value of unvisited moves at the root, if it should differ from the
+    /// rest of the tree.
+    pub fn root_fpu(mut self, fpu: Fpu) -> Self {
+        self.root_fpu = Some(fpu);
+        self
+    }
+
+    pub fn c_base(&self) -> f64 {
+        self.c_base
+    }
+
+    pub fn c_init(&self) -> f64 {
+        self.c_init
+    }
+
+    /// The exploration coefficient after `parent_visits` visits of the parent.
+    pub fn exploration_coefficient(&self, parent_visits: u64) -> f64 {
+        ((1.0 + parent_visits as f64 + self.c_base) / self.c_base).ln() + self.c_init
+    }
+
+    fn fpu_at(&self, is_root: bool) -> Fpu {
+        if is_root {
+            self.root_fpu.unwrap_or(self.fpu)
+        } else {
+            self.fpu
+        }
+    }
+}
+
 impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for UCTPolicy {
     type ThreadLocalData = PolicyRng;
     type MoveEvaluation = ();
 
+    fn seed_thread_data(&self, data: &mut PolicyRng, seed: u64) {
+        *data = PolicyRng::from_seed(seed);
+    }
+
     fn choose_child<'a, MoveIter>(
         &self,
         moves: MoveIter,
@@ -104,7 +544,7 @@ impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for UCTPolicy {
                 let child_visits = mov.visits();
                 // http://mcts.ai/pubs/mcts-survey-master.pdf
                 if child_visits == 0 {
-                    std::f64::INFINITY
+                    f64::INFINITY
                 } else {
                     let explore_term = 2.0 * (ln_adjusted_total / child_visits as f64).sqrt();
                     let mean_action_value = sum_rewards as f64 / child_visits as f64;
@@ -115,10 +555,77 @@ impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for UCTPolicy {
     }
 }
 
+impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for UCB1TunedPolicy {
+    type ThreadLocalData = PolicyRng;
+    type MoveEvaluation = ();
+
+    fn seed_thread_data(&self, data: &mut PolicyRng, seed: u64) {
+        *data = PolicyRng::from_seed(seed);
+    }
+
+    fn choose_child<'a, MoveIter>(
+        &self,
+        moves: MoveIter,
+        mut handle: SearchHandle<Spec>,
+    ) -> &'a MoveInfo<Spec>
+    where
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+    {
+        let total_visits = moves.clone().map(|x| x.visits()).sum::<u64>();
+        let ln_adjusted_total = ((total_visits + 1) as f64).ln();
+        let range = (self.win - self.loss) as f64;
+        handle
+            .thread_data()
+            .policy_data
+            .select_by_key(moves, |mov| {
+                let child_visits = mov.visits();
+                if child_visits == 0 {
+                    f64::INFINITY
+                } else {
+                    let n = child_visits as f64;
+                    let mean = (mov.mean_reward() - self.loss as f64) / range;
+                    let variance = mov.reward_variance() / (range * range);
+                    let variance_bound = variance + (2.0 * ln_adjusted_total / n).sqrt();
+                    let explore_term = (ln_adjusted_total / n * variance_bound.min(0.25)).sqrt();
+                    self.exploration_constant * explore_term + mean
+                }
+            })
+            .unwrap()
+    }
+}
+
+impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for ThompsonPolicy {
+    type ThreadLocalData = PolicyRng;
+    type MoveEvaluation = ();
+
+    fn seed_thread_data(&self, data: &mut PolicyRng, seed: u64) {
+        *data = PolicyRng::from_seed(seed);
+    }
+
+    fn choose_child<'a, MoveIter>(
+        &self,
+        moves: MoveIter,
+        mut handle: SearchHandle<Spec>,
+    ) -> &'a MoveInfo<Spec>
+    where
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+    {
+        let rng = &mut handle.thread_data().policy_data;
+        let samples: Vec<_> = moves.map(|mov| (mov, self.sample(mov, rng))).collect();
+        rng.select_by_key(samples.into_iter(), |&(_, sample)| sample)
+            .unwrap()
+            .0
+    }
+}
+
 impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for AlphaGoPolicy {
     type ThreadLocalData = PolicyRng;
     type MoveEvaluation = f64;
 
+    fn seed_thread_data(&self, data: &mut PolicyRng, seed: u64) {
+        *data = PolicyRng::from_seed(seed);
+    }
+
     fn choose_child<'a, MoveIter>(
         &self,
         moves: MoveIter,
@@ -130,73 +637,774 @@ impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for AlphaGoPolicy {
         let total_visits = moves.clone().map(|x| x.visits()).sum::<u64>() + 1;
         let sqrt_total_visits = (total_visits as f64).sqrt();
         let explore_coef = self.exploration_constant * sqrt_total_visits;
+        let fpu_value = self.fpu_at(handle.is_root()).value(moves.clone());
         handle
             .thread_data()
             .policy_data
             .select_by_key(moves, |mov| {
-                let sum_rewards = mov.sum_rewards() as f64;
                 let child_visits = mov.visits();
-                let policy_evaln = *mov.move_evaluation() as f64;
-                (sum_rewards + explore_coef * policy_evaln) * self.reciprocal(child_visits as usize)
+                let policy_evaln = *mov.move_evaluation();
+                if child_visits == 0 {
+                    fpu_value + explore_coef * policy_evaln
+                } else {
+                    let sum_rewards = mov.sum_rewards() as f64;
+                    (sum_rewards + explore_coef * policy_evaln)
+                        * self.reciprocal(child_visits as usize)
+                }
             })
             .unwrap()
     }
 
-    fn validate_evaluations(&self, evalns: &[f64]) {
-        for &x in evalns {
-            assert!(
-                x >= -1e-6,
-                "Move evaluation is {} (must be non-negative)",
-                x
-            );
-        }
-        if evalns.len() >= 1 {
-            let evaln_sum: f64 = evalns.iter().sum();
-            assert!(
-                (evaln_sum - 1.0).abs() < 0.1,
-                "Sum of evaluations is {} (should sum to 1)",
-                evaln_sum
-            );
-        }
+    fn noisy_evaluation(&self, evaln: &f64, noise: f64, epsilon: f64) -> Option<f64> {
+        Some((1.0 - epsilon) * evaln + epsilon * noise)
     }
-}
 
-#[derive(Clone)]
-pub struct PolicyRng {
-    rng: XorShiftRng,
+    fn validate_evaluations(&self, evalns: &[f64]) {
+        validate_priors(evalns);
+    }
 }
 
-impl PolicyRng {
-    pub fn new() -> Self {
-        let rng = SeedableRng::from_entropy();
+impl<Spec: MCTS<TreePolicy = Self>> TreePolicy<Spec> for AlphaZeroPolicy {
+    type ThreadLocalData = PolicyRng;
+    type MoveEvaluation = f64;
 
-        // let rng = SeedableRng::from_seed([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16]);
-        Self { rng }
+    fn seed_thread_data(&self, data: &mut PolicyRng, seed: u64) {
+        *data = PolicyRng::from_seed(seed);
     }
 
-    pub fn select_by_key<T, Iter, KeyFn>(&mut self, elts: Iter, mut key_fn: KeyFn) -> Option<T>
+    fn choose_child<'a, MoveIter>(
+        &self,
+        moves: MoveIter,
+        mut handle: SearchHandle<Spec>,
+    ) -> &'a MoveInfo<Spec>
     where
-        Iter: Iterator<Item = T>,
-        KeyFn: FnMut(&T) -> f64,
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
     {
-        let mut choice = None;
-        let mut num_optimal: f64 = 0.;
-        let mut best_so_far: f64 = std::f64::NEG_INFINITY;
-        for elt in elts {
-            let score = key_fn(&elt);
-            if score > best_so_far {
-                choice = Some(elt);
-                num_optimal = 1.;
-                best_so_far = score;
-            } else if score == best_so_far {
-                num_optimal += 1.;
+        // the parent's visits are its children's plus the one that expanded it
+        let parent_visits = moves.clone().map(|x| x.visits()).sum::<u64>() + 1;
+        let explore_coef =
+            self.exploration_coefficient(parent_visits) * (parent_visits as f64).sqrt();
+        let fpu_value = self.fpu_at(handle.is_root()).value(moves.clone()) / self.reward_scale;
+        handle
+            .thread_data()
+            .policy_data
+            .select_by_key(moves, |mov| {
+                let child_visits = mov.visits();
+                let q = if child_visits == 0 {
+                    fpu_value
+                } else {
+                    mov.mean_reward() / self.reward_scale
+                };
+                let u = explore_coef * *mov.move_evaluation() / (1 + child_visits) as f64;
+                q + u
+            })
+            .unwrap()
+    }
 
-                if self.rng.gen_bool(1. / num_optimal) {
-                    choice = Some(elt);
-                }
-            }
-        }
-        choice
+    fn noisy_evaluation(&self, evaln: &f64, noise: f64, epsilon: f64) -> Option<f64> {
+        Some((1.0 - epsilon) * evaln + epsilon * noise)
+    }
+
+    fn validate_evaluations(&self, evalns: &[f64]) {
+        validate_priors(evalns);
+    }
+}
+
+impl<Spec> TreePolicy<Spec> for Exp3Policy
+where
+    Spec: MCTS<TreePolicy = Self>,
+    Spec::NodeData: AsRef<BanditData>,
+    Move<Spec>: JointMove,
+{
+    type ThreadLocalData = BanditThreadData;
+    type MoveEvaluation = ();
+
+    fn seed_thread_data(&self, data: &mut BanditThreadData, seed: u64) {
+        data.rng = PolicyRng::from_seed(seed);
+    }
+
+    fn choose_child<'a, MoveIter>(
+        &self,
+        moves: MoveIter,
+        handle: SearchHandle<Spec>,
+    ) -> &'a MoveInfo<Spec>
+    where
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+    {
+        choose_joint_move(self, moves, handle)
+    }
+
+    fn on_backpropagation<'a, MoveIter>(
+        &self,
+        moves: MoveIter,
+        playout: PlayoutMoves<Spec>,
+        reward: i64,
+        handle: SearchHandle<Spec>,
+    ) where
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+        Spec: 'a,
+    {
+        update_bandits(self, moves, playout.choice(), reward, handle)
+    }
+
+    fn mixed_strategy<'a, MoveIter>(
+        &self,
+        moves: MoveIter,
+        node: NodeHandle<Spec>,
+    ) -> Option<Vec<f64>>
+    where
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+        Spec: 'a,
+    {
+        Some(joint_strategy(moves, &node))
+    }
+}
+
+impl<Spec> TreePolicy<Spec> for RegretMatchingPolicy
+where
+    Spec: MCTS<TreePolicy = Self>,
+    Spec::NodeData: AsRef<BanditData>,
+    Move<Spec>: JointMove,
+{
+    type ThreadLocalData = BanditThreadData;
+    type MoveEvaluation = ();
+
+    fn seed_thread_data(&self, data: &mut BanditThreadData, seed: u64) {
+        data.rng = PolicyRng::from_seed(seed);
+    }
+
+    fn choose_child<'a, MoveIter>(
+        &self,
+        moves: MoveIter,
+        handle: SearchHandle<Spec>,
+    ) -> &'a MoveInfo<Spec>
+    where
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+    {
+        choose_joint_move(self, moves, handle)
+    }
+
+    fn on_backpropagation<'a, MoveIter>(
+        &self,
+        moves: MoveIter,
+        playout: PlayoutMoves<Spec>,
+        reward: i64,
+        handle: SearchHandle<Spec>,
+    ) where
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+        Spec: 'a,
+    {
+        update_bandits(self, moves, playout.choice(), reward, handle)
+    }
+
+    fn mixed_strategy<'a, MoveIter>(
+        &self,
+        moves: MoveIter,
+        node: NodeHandle<Spec>,
+    ) -> Option<Vec<f64>>
+    where
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+        Spec: 'a,
+    {
+        Some(joint_strategy(moves, &node))
+    }
+}
+
+impl<Spec> TreePolicy<Spec> for RAVEPolicy
+where
+    Spec: MCTS<TreePolicy = Self>,
+    Spec::NodeData: AsRef<RaveData>,
+    Move<Spec>: Hash + Eq,
+    Player<Spec>: PartialEq,
+{
+    type ThreadLocalData = RaveThreadData<Spec>;
+    type MoveEvaluation = ();
+
+    fn seed_thread_data(&self, data: &mut RaveThreadData<Spec>, seed: u64) {
+        data.rng = PolicyRng::from_seed(seed);
+    }
+
+    fn choose_child<'a, MoveIter>(
+        &self,
+        moves: MoveIter,
+        mut handle: SearchHandle<Spec>,
+    ) -> &'a MoveInfo<Spec>
+    where
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+    {
+        let node = handle.node();
+        let amaf = node.data().as_ref().stats(&node);
+        let total_visits = moves.clone().map(|x| x.visits()).sum::<u64>();
+        let ln_adjusted_total = ((total_visits + 1) as f64).ln();
+        let is_root = handle.is_root();
+        let data = &mut handle.thread_data().policy_data;
+        if is_root {
+            // left over from the previous playout
+            data.rollout.clear();
+        }
+        data.rng
+            .select_by_key(moves, |mov| {
+                let visits = mov.visits();
+                let (amaf_visits, amaf_mean) = amaf[node.move_index(mov).unwrap()].get();
+                if visits == 0 && amaf_visits == 0 {
+                    return f64::INFINITY;
+                }
+                let beta = if amaf_visits == 0 {
+                    0.0
+                } else if visits == 0 {
+                    1.0
+                } else {
+                    self.schedule.beta(visits, amaf_visits)
+                };
+                let value = (1.0 - beta) * mov.mean_reward() + beta * amaf_mean;
+                let explore_term = (ln_adjusted_total / visits.max(1) as f64).sqrt();
+                value + self.exploration_constant * explore_term
+            })
+            .unwrap()
+    }
+
+    fn on_backpropagation<'a, MoveIter>(
+        &self,
+        _moves: MoveIter,
+        playout: PlayoutMoves<Spec>,
+        reward: i64,
+        mut handle: SearchHandle<Spec>,
+    ) where
+        MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+        Spec: 'a,
+    {
+        let node = handle.node();
+        let amaf = node.data().as_ref().stats(&node);
+        let player = playout.iter().next().unwrap().1;
+        let rollout = &handle.thread_data().policy_data.rollout;
+        // a move counts once per playout, however often it was repeated
+        let played: HashSet<&Move<Spec>> = playout
+            .iter()
+            .map(|(mov, x)| (mov.get_move(), x))
+            .chain(rollout.iter().map(|(mov, x)| (mov, x)))
+            .filter(|&(_, x)| x == player)
+            .map(|(mov, _)| mov)
+            .collect();
+        for (stats, mov) in amaf.iter().zip(node.moves()) {
+            if played.contains(mov.get_move()) {
+                stats.add(reward);
+            }
+        }
+    }
+}
+
+fn validate_priors(evalns: &[f64]) {
+    for &x in evalns {
+        assert!(
+            x >= -1e-6,
+            "Move evaluation is {} (must be non-negative)",
+            x
+        );
+    }
+    if !evalns.is_empty() {
+        let evaln_sum: f64 = evalns.iter().sum();
+        assert!(
+            (evaln_sum - 1.0).abs() < 0.1,
+            "Sum of evaluations is {} (should sum to 1)",
+            evaln_sum
+        );
+    }
+}
+
+/// A move of a simultaneous-move game, for `Exp3Policy` and `RegretMatchingPolicy`.
+/// It is made of one action by each of two players: the first is the node's
+/// `current_player`, and the second player's reward is the opposite of the first's.
+///
+/// A node's moves must include every combination of the players' actions. Nodes where
+/// only the first player chooses can give the second player a single action.
+pub trait JointMove {
+    /// The indices of the first and the second player's actions.
+    fn actions(&self) -> (usize, usize);
+}
+
+/// The node data `Exp3Policy` and `RegretMatchingPolicy` need. Specs that use them can
+/// set `NodeData = BanditData`, or embed it in their own node data and implement
+/// `AsRef<BanditData>`.
+#[derive(Debug, Default)]
+pub struct BanditData {
+    // one arm for each action of each player, created by the first visit, since
+    // `Default` doesn't know the number of actions
+    arms: OnceLock<[Vec<BanditArm>; 2]>,
+}
+
+#[derive(Debug)]
+struct BanditArm {
+    // the estimated cumulative reward for EXP3, or the cumulative regret for regret
+    // matching, as the bits of an f64
+    value: AtomicU64,
+    // the sum of the action's probabilities over all visits, as the bits of an f64
+    strategy_sum: AtomicU64,
+}
+
+impl BanditArm {
+    fn new() -> Self {
+        Self {
+            value: AtomicU64::new(0f64.to_bits()),
+            strategy_sum: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+}
+
+impl BanditData {
+    fn arms<Spec: MCTS>(&self, node: &NodeHandle<Spec>, player: usize) -> &[BanditArm]
+    where
+        Move<Spec>: JointMove,
+    {
+        let arms = self.arms.get_or_init(|| {
+            let num_actions = |player| {
+                node.moves()
+                    .map(|x| action(x, player) + 1)
+                    .max()
+                    .unwrap_or(0)
+            };
+            [0, 1].map(|player| (0..num_actions(player)).map(|_| BanditArm::new()).collect())
+        });
+        &arms[player]
+    }
+}
+
+impl AsRef<BanditData> for BanditData {
+    fn as_ref(&self) -> &BanditData {
+        self
+    }
+}
+
+/// The thread-local data of `RAVEPolicy`.
+pub struct RaveThreadData<Spec: MCTS> {
+    rng: PolicyRng,
+    // the moves the evaluator simulated in the current playout
+    rollout: Vec<(Move<Spec>, Player<Spec>)>,
+}
+
+impl<Spec: MCTS> Default for RaveThreadData<Spec> {
+    fn default() -> Self {
+        Self {
+            rng: PolicyRng::default(),
+            rollout: Vec::new(),
+        }
+    }
+}
+
+impl<Spec: MCTS> RaveThreadData<Spec> {
+    /// Reports a move that the evaluator simulated past the end of the tree, so that it
+    /// counts towards the AMAF values. Evaluators that play out random games call this
+    /// through `SearchHandle::thread_data` for each move, in order.
+    pub fn add_rollout_move(&mut self, mov: Move<Spec>, player: Player<Spec>) {
+        self.rollout.push((mov, player));
+    }
+}
+
+/// The thread-local data of `Exp3Policy` and `RegretMatchingPolicy`.
+#[derive(Clone, Default)]
+pub struct BanditThreadData {
+    rng: PolicyRng,
+    // for each node of the current playout, its address and the probability each
+    // player sampled their action with, in the order the nodes were visited
+    sampled: Vec<(usize, [f64; 2])>,
+}
+
+// the bandit algorithm each player runs at every node
+trait Bandit {
+    fn probabilities(&self, values: &[f64]) -> Vec<f64>;
+
+    // how much the value of each of `num_actions` arms changes after the action at
+    // `choice`, sampled with `probability`, earned `reward`
+    fn value_deltas(
+        &self,
+        num_actions: usize,
+        choice: usize,
+        probability: f64,
+        reward: f64,
+    ) -> Vec<f64>;
+
+    fn opponent_reward(&self, reward: i64) -> i64;
+}
+
+impl Bandit for Exp3Policy {
+    fn probabilities(&self, values: &[f64]) -> Vec<f64> {
+        let k = values.len() as f64;
+        let eta = self.gamma / k;
+        // relative to the largest, so the exponentials can't overflow
+        let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let weights: Vec<f64> = values.iter().map(|x| (eta * (x - max)).exp()).collect();
+        let total: f64 = weights.iter().sum();
+        weights
+            .iter()
+            .map(|x| (1.0 - self.gamma) * x / total + self.gamma / k)
+            .collect()
+    }
+
+    fn value_deltas(
+        &self,
+        num_actions: usize,
+        choice: usize,
+        probability: f64,
+        reward: f64,
+    ) -> Vec<f64> {
+        let scaled_reward =
+            ((reward - self.loss as f64) / (self.win - self.loss) as f64).clamp(0.0, 1.0);
+        (0..num_actions)
+            .map(|i| {
+                if i == choice {
+                    scaled_reward / probability
+                } else {
+                    0.0
+                }
+            })
+            .collect()
+    }
+
+    fn opponent_reward(&self, reward: i64) -> i64 {
+        self.loss + self.win - reward
+    }
+}
+
+impl Bandit for RegretMatchingPolicy {
+    fn probabilities(&self, values: &[f64]) -> Vec<f64> {
+        let k = values.len() as f64;
+        let total: f64 = values.iter().map(|x| x.max(0.0)).sum();
+        values
+            .iter()
+            .map(|x| {
+                let p = if total > 0.0 {
+                    x.max(0.0) / total
+                } else {
+                    1.0 / k
+                };
+                (1.0 - self.gamma) * p + self.gamma / k
+            })
+            .collect()
+    }
+
+    // The reward of each action is estimated by importance sampling: the reward divided
+    // by its probability for the chosen action, and zero for the others. The regret of
+    // an action is how much more it would have earned than the reward.
+    fn value_deltas(
+        &self,
+        num_actions: usize,
+        choice: usize,
+        probability: f64,
+        reward: f64,
+    ) -> Vec<f64> {
+        (0..num_actions)
+            .map(|i| {
+                let estimate = if i == choice {
+                    reward / probability
+                } else {
+                    0.0
+                };
+                estimate - reward
+            })
+            .collect()
+    }
+
+    fn opponent_reward(&self, reward: i64) -> i64 {
+        -reward
+    }
+}
+
+fn action<Spec: MCTS>(mov: &MoveInfo<Spec>, player: usize) -> usize
+where
+    Move<Spec>: JointMove,
+{
+    let actions = mov.get_move().actions();
+    if player == 0 {
+        actions.0
+    } else {
+        actions.1
+    }
+}
+
+// the actions of `player` in `moves`, in increasing order
+fn player_actions<'a, Spec, MoveIter>(moves: MoveIter, player: usize) -> Vec<usize>
+where
+    Spec: MCTS + 'a,
+    Move<Spec>: JointMove,
+    MoveIter: Iterator<Item = &'a MoveInfo<Spec>>,
+{
+    let mut actions: Vec<usize> = moves.map(|x| action(x, player)).collect();
+    actions.sort_unstable();
+    actions.dedup();
+    actions
+}
+
+fn load_f64(x: &AtomicU64) -> f64 {
+    f64::from_bits(x.load(Ordering::Relaxed))
+}
+
+fn add_f64(x: &AtomicU64, y: f64) {
+    let _ = x.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |old| {
+        Some((f64::from_bits(old) + y).to_bits())
+    });
+}
+
+// the probabilities `bandit` gives the actions of `player`, in increasing order of
+// action
+fn action_probabilities<'a, Spec, B, MoveIter>(
+    bandit: &B,
+    moves: MoveIter,
+    node: &NodeHandle<Spec>,
+    player: usize,
+) -> (Vec<usize>, Vec<f64>)
+where
+    Spec: MCTS + 'a,
+    Spec::NodeData: AsRef<BanditData>,
+    Move<Spec>: JointMove,
+    B: Bandit,
+    MoveIter: Iterator<Item = &'a MoveInfo<Spec>>,
+{
+    let arms = node.data().as_ref().arms(node, player);
+    let actions = player_actions(moves, player);
+    let values: Vec<f64> = actions.iter().map(|&x| load_f64(&arms[x].value)).collect();
+    let probabilities = bandit.probabilities(&values);
+    (actions, probabilities)
+}
+
+// samples an action for each player from their bandit, and returns the joint move
+fn choose_joint_move<'a, Spec, B, MoveIter>(
+    bandit: &B,
+    moves: MoveIter,
+    mut handle: SearchHandle<Spec>,
+) -> &'a MoveInfo<Spec>
+where
+    Spec: MCTS<TreePolicy = B>,
+    Spec::NodeData: AsRef<BanditData>,
+    Move<Spec>: JointMove,
+    B: Bandit + TreePolicy<Spec, ThreadLocalData = BanditThreadData>,
+    MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+{
+    let node = handle.node();
+    let is_root = handle.is_root();
+    let data = &mut handle.thread_data().policy_data;
+    if is_root {
+        // left over from playouts that failed before backpropagation
+        data.sampled.clear();
+    }
+    let mut chosen = [0; 2];
+    let mut sampled = [1.0; 2];
+    for player in 0..2 {
+        let (actions, probabilities) = action_probabilities(bandit, moves.clone(), &node, player);
+        let arms = node.data().as_ref().arms(&node, player);
+        for (&x, &p) in actions.iter().zip(&probabilities) {
+            add_f64(&arms[x].strategy_sum, p);
+        }
+        let mut sample = data.rng.gen::<f64>();
+        chosen[player] = *actions.last().unwrap();
+        sampled[player] = *probabilities.last().unwrap();
+        for (&x, &p) in actions.iter().zip(&probabilities) {
+            if sample < p {
+                chosen[player] = x;
+                sampled[player] = p;
+                break;
+            }
+            sample -= p;
+        }
+    }
+    data.sampled.push((node.into_raw() as usize, sampled));
+    moves
+        .into_iter()
+        .find(|x| x.get_move().actions() == (chosen[0], chosen[1]))
+        .unwrap_or_else(|| panic!("no move has the actions {:?}", chosen))
+}
+
+fn update_bandits<'a, Spec, B, MoveIter>(
+    bandit: &B,
+    moves: MoveIter,
+    choice: &MoveInfo<Spec>,
+    reward: i64,
+    mut handle: SearchHandle<Spec>,
+) where
+    Spec: MCTS<TreePolicy = B> + 'a,
+    Spec::NodeData: AsRef<BanditData>,
+    Move<Spec>: JointMove,
+    B: Bandit + TreePolicy<Spec, ThreadLocalData = BanditThreadData>,
+    MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+{
+    let node = handle.node();
+    // Backpropagation visits the nodes in the reverse order of `choose_joint_move`, so
+    // this node's probabilities are on top of the stack. They are missing only if the
+    // playout passed through the root again, which cleared the stack.
+    let key = node.into_raw() as usize;
+    let stack = &mut handle.thread_data().policy_data.sampled;
+    let mut sampled = None;
+    while let Some((x, probabilities)) = stack.pop() {
+        if x == key {
+            sampled = Some(probabilities);
+            break;
+        }
+    }
+
+    let rewards = [reward, bandit.opponent_reward(reward)];
+    for (player, &reward) in rewards.iter().enumerate() {
+        let arms = node.data().as_ref().arms(&node, player);
+        let actions = player_actions(moves.clone(), player);
+        let choice = actions
+            .iter()
+            .position(|&x| x == action(choice, player))
+            .unwrap();
+        let probability = match sampled {
+            Some(x) => x[player],
+            None => action_probabilities(bandit, moves.clone(), &node, player).1[choice],
+        };
+        let deltas = bandit.value_deltas(actions.len(), choice, probability, reward as f64);
+        for (&x, delta) in actions.iter().zip(deltas) {
+            add_f64(&arms[x].value, delta);
+        }
+    }
+}
+
+// the probability of each joint move under the players' average strategies
+fn joint_strategy<'a, Spec, MoveIter>(moves: MoveIter, node: &NodeHandle<Spec>) -> Vec<f64>
+where
+    Spec: MCTS + 'a,
+    Spec::NodeData: AsRef<BanditData>,
+    Move<Spec>: JointMove,
+    MoveIter: Iterator<Item = &'a MoveInfo<Spec>> + Clone,
+{
+    let strategies: Vec<Vec<(usize, f64)>> = (0..2)
+        .map(|player| {
+            let arms = node.data().as_ref().arms(node, player);
+            let actions = player_actions(moves.clone(), player);
+            let sums: Vec<f64> = actions
+                .iter()
+                .map(|&x| load_f64(&arms[x].strategy_sum))
+                .collect();
+            let total: f64 = sums.iter().sum();
+            let n = actions.len() as f64;
+            actions
+                .into_iter()
+                .zip(sums)
+                .map(|(x, sum)| (x, if total == 0.0 { 1.0 / n } else { sum / total }))
+                .collect()
+        })
+        .collect();
+    let probability = |player: usize, mov: &MoveInfo<Spec>| {
+        let x = action(mov, player);
+        strategies[player]
+            .iter()
+            .find(|&&(y, _)| y == x)
+            .map_or(0.0, |&(_, p)| p)
+    };
+    moves
+        .map(|x| probability(0, x) * probability(1, x))
+        .collect()
+}
+
+/// The node data `RAVEPolicy` needs: the AMAF statistics of each of the node's moves.
+/// Specs that use it can set `NodeData = RaveData`, or embed it in their own node data
+/// and implement `AsRef<RaveData>`.
+#[derive(Debug, Default)]
+pub struct RaveData {
+    // created by the first visit, since `Default` doesn't know the number of moves
+    amaf: OnceLock<Vec<AmafStats>>,
+}
+
+#[derive(Debug)]
+struct AmafStats {
+    visits: AtomicUsize,
+    sum_rewards: AtomicI64,
+}
+
+impl AmafStats {
+    fn add(&self, reward: i64) {
+        self.sum_rewards
+            .fetch_add(reward as FakeI64, Ordering::Relaxed);
+        self.visits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // the visits and mean reward
+    fn get(&self) -> (u64, f64) {
+        let visits = self.visits.load(Ordering::Relaxed) as u64;
+        let sum_rewards = self.sum_rewards.load(Ordering::Relaxed) as i64;
+        if visits == 0 {
+            (0, 0.0)
+        } else {
+            (visits, sum_rewards as f64 / visits as f64)
+        }
+    }
+}
+
+impl RaveData {
+    fn stats<Spec: MCTS>(&self, node: &NodeHandle<Spec>) -> &[AmafStats] {
+        self.amaf.get_or_init(|| {
+            node.moves()
+                .map(|_| AmafStats {
+                    visits: AtomicUsize::new(0),
+                    sum_rewards: AtomicI64::new(0),
+                })
+                .collect()
+        })
+    }
+}
+
+impl AsRef<RaveData> for RaveData {
+    fn as_ref(&self) -> &RaveData {
+        self
+    }
+}
+
+#[derive(Clone)]
+pub struct PolicyRng {
+    rng: XorShiftRng,
+}
+
+impl PolicyRng {
+    pub fn new() -> Self {
+        let rng = SeedableRng::from_entropy();
+        Self { rng }
+    }
+
+    pub fn from_seed(seed: u64) -> Self {
+        let rng = SeedableRng::seed_from_u64(seed);
+        Self { rng }
+    }
+
+    pub fn select_by_key<T, Iter, KeyFn>(&mut self, elts: Iter, mut key_fn: KeyFn) -> Option<T>
+    where
+        Iter: Iterator<Item = T>,
+        KeyFn: FnMut(&T) -> f64,
+    {
+        let mut choice = None;
+        let mut num_optimal: f64 = 0.;
+        let mut best_so_far: f64 = f64::NEG_INFINITY;
+        for elt in elts {
+            let score = key_fn(&elt);
+            if score > best_so_far {
+                choice = Some(elt);
+                num_optimal = 1.;
+                best_so_far = score;
+            } else if score == best_so_far {
+                num_optimal += 1.;
+
+                if self.rng.gen_bool(1. / num_optimal) {
+                    choice = Some(elt);
+                }
+            }
+        }
+        choice
+    }
+}
+
+impl RngCore for PolicyRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
     }
 }
 
@@ -205,3 +1413,360 @@ impl Default for PolicyRng {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test_game::*;
+
+    // A single simultaneous move with the payoffs `PAYOFFS[first][second]` for the first
+    // player. In the equilibrium both players play their first action a third of the
+    // time.
+    const PAYOFFS: [[i64; 2]; 2] = [[300, -100], [-100, 100]];
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct MatrixGame(Option<(usize, usize)>);
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Actions(usize, usize);
+
+    impl JointMove for Actions {
+        fn actions(&self) -> (usize, usize) {
+            (self.0, self.1)
+        }
+    }
+
+    impl GameState for MatrixGame {
+        type Move = Actions;
+        type Player = ();
+        type MoveList = Vec<Actions>;
+
+        fn current_player(&self) {}
+
+        fn available_moves(&self) -> Vec<Actions> {
+            match self.0 {
+                Some(_) => vec![],
+                None => vec![Actions(0, 0), Actions(0, 1), Actions(1, 0), Actions(1, 1)],
+            }
+        }
+
+        fn make_move(&mut self, mov: &Actions) {
+            self.0 = Some(mov.actions());
+        }
+    }
+
+    struct MatrixEvaluator;
+
+    macro_rules! matrix_spec {
+        ($spec:ident, $policy:ty) => {
+            #[derive(Debug)]
+            struct $spec;
+
+            impl MCTS for $spec {
+                type State = MatrixGame;
+                type Eval = MatrixEvaluator;
+                type TreePolicy = $policy;
+                type NodeData = BanditData;
+                type TranspositionTable = ();
+                type ExtraThreadData = ();
+            }
+
+            impl Evaluator<$spec> for MatrixEvaluator {
+                type StateEvaluation = i64;
+
+                fn evaluate_new_state(
+                    &self,
+                    state: &MatrixGame,
+                    moves: &Vec<Actions>,
+                    _: Option<SearchHandle<$spec>>,
+                ) -> (Vec<()>, i64) {
+                    let evaln = state.0.map_or(0, |(x, y)| PAYOFFS[x][y]);
+                    (vec![(); moves.len()], evaln)
+                }
+
+                fn interpret_evaluation_for_player(&self, evaln: &i64, _: &()) -> i64 {
+                    *evaln
+                }
+
+                fn evaluate_existing_state(
+                    &self,
+                    _: &MatrixGame,
+                    evaln: &i64,
+                    _: SearchHandle<$spec>,
+                ) -> i64 {
+                    *evaln
+                }
+            }
+        };
+    }
+
+    matrix_spec!(Exp3MCTS, Exp3Policy);
+    matrix_spec!(RegretMatchingMCTS, RegretMatchingPolicy);
+
+    // the probability of the first action of each player in the root strategy
+    fn first_actions<Spec>(manager: &mut MCTSManager<Spec>) -> (f64, f64)
+    where
+        Spec: MCTS<State = MatrixGame> + 'static,
+        ThreadData<Spec>: Default,
+    {
+        manager.seed(1);
+        manager.playout_n(100_000).unwrap();
+        let strategy = manager.root_strategy();
+        let probability = |player| {
+            strategy
+                .iter()
+                .filter(|(x, _)| {
+                    let actions = x.get_move().actions();
+                    [actions.0, actions.1][player] == 0
+                })
+                .map(|&(_, p)| p)
+                .sum()
+        };
+        (probability(0), probability(1))
+    }
+
+    #[test]
+    fn exp3_approaches_equilibrium() {
+        let policy = Exp3Policy::new(0.1, -100, 300);
+        let mut manager = MCTSManager::new(MatrixGame(None), Exp3MCTS, MatrixEvaluator, policy, ());
+        let (first, second) = first_actions(&mut manager);
+        assert!((first - 1.0 / 3.0).abs() < 0.05, "{}", first);
+        assert!((second - 1.0 / 3.0).abs() < 0.05, "{}", second);
+    }
+
+    #[test]
+    fn regret_matching_approaches_equilibrium() {
+        let policy = RegretMatchingPolicy::new(0.1);
+        let mut manager = MCTSManager::new(
+            MatrixGame(None),
+            RegretMatchingMCTS,
+            MatrixEvaluator,
+            policy,
+            (),
+        );
+        let (first, second) = first_actions(&mut manager);
+        assert!((first - 1.0 / 3.0).abs() < 0.05, "{}", first);
+        assert!((second - 1.0 / 3.0).abs() < 0.05, "{}", second);
+    }
+
+    #[test]
+    fn low_prior_unvisited_move_loses_to_visited_move() {
+        let policy = AlphaGoPolicy::new(1.0).fpu(Fpu::loss(0));
+        let mut manager = alphago_manager(policy);
+        manager.restrict_root_moves(&[Add::One]);
+        manager.playout_n(1).unwrap();
+        manager.clear_root_restriction();
+
+        // `Two` has the lower prior, and an unvisited move is worth a loss
+        manager.playout_n(1).unwrap();
+        let visits: Vec<_> = manager
+            .tree()
+            .root_moves()
+            .map(|x| (x.get_move().clone(), x.visits()))
+            .collect();
+        assert_eq!(visits, [(Add::One, 2), (Add::Two, 0)]);
+    }
+
+    #[test]
+    fn puct_coefficient_grows_with_visits() {
+        let policy = AlphaZeroPolicy::new(19652.0, 1.25);
+        // ln((1 + N + c_base) / c_base) + c_init
+        assert!((policy.exploration_coefficient(0) - 1.25).abs() < 1e-4);
+        let doubled = policy.exploration_coefficient(19651);
+        assert!((doubled - (1.25 + 2f64.ln())).abs() < 1e-12, "{}", doubled);
+        let quadrupled = policy.exploration_coefficient(3 * 19652 - 1);
+        assert!((quadrupled - (1.25 + 4f64.ln())).abs() < 1e-12);
+
+        let mut last = 0.0;
+        for visits in (0..10).map(|x| 10u64.pow(x)) {
+            let coefficient = policy.exploration_coefficient(visits);
+            assert!(coefficient > last);
+            last = coefficient;
+        }
+    }
+
+    // A bandit with two arms, as a game of one move. The arms pay one with the
+    // probabilities `WIN_RATES` and zero otherwise.
+    const WIN_RATES: [f64; 2] = [0.4, 0.6];
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct BanditGame(Option<usize>);
+
+    impl GameState for BanditGame {
+        type Move = usize;
+        type Player = ();
+        type MoveList = Vec<usize>;
+
+        fn current_player(&self) {}
+
+        fn available_moves(&self) -> Vec<usize> {
+            match self.0 {
+                Some(_) => vec![],
+                None => vec![0, 1],
+            }
+        }
+
+        fn make_move(&mut self, mov: &usize) {
+            self.0 = Some(*mov);
+        }
+    }
+
+    // Pays out deterministically: the fractional parts of multiples of the golden ratio
+    // are spread evenly, so each arm pays its win rate over any long run of pulls.
+    #[derive(Default)]
+    struct BanditEvaluator {
+        pulls: [AtomicUsize; 2],
+    }
+
+    impl BanditEvaluator {
+        fn pull(&self, state: &BanditGame) -> i64 {
+            match state.0 {
+                Some(arm) => {
+                    let pull = self.pulls[arm].fetch_add(1, Ordering::Relaxed);
+                    let x = (pull as f64 * 0.618_033_988_749_895).fract();
+                    (x < WIN_RATES[arm]) as i64
+                }
+                None => 0,
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    struct ThompsonMCTS;
+
+    impl MCTS for ThompsonMCTS {
+        type State = BanditGame;
+        type Eval = BanditEvaluator;
+        type TreePolicy = ThompsonPolicy;
+        type NodeData = ();
+        type TranspositionTable = ();
+        type ExtraThreadData = ();
+    }
+
+    impl Evaluator<ThompsonMCTS> for BanditEvaluator {
+        type StateEvaluation = i64;
+
+        fn evaluate_new_state(
+            &self,
+            state: &BanditGame,
+            moves: &Vec<usize>,
+            _: Option<SearchHandle<ThompsonMCTS>>,
+        ) -> (Vec<()>, i64) {
+            (vec![(); moves.len()], self.pull(state))
+        }
+
+        fn interpret_evaluation_for_player(&self, evaln: &i64, _: &()) -> i64 {
+            *evaln
+        }
+
+        fn evaluate_existing_state(
+            &self,
+            state: &BanditGame,
+            _: &i64,
+            _: SearchHandle<ThompsonMCTS>,
+        ) -> i64 {
+            self.pull(state)
+        }
+    }
+
+    #[test]
+    fn thompson_converges_on_better_arm() {
+        for posterior in [
+            Posterior::Beta { loss: 0, win: 1 },
+            Posterior::Gaussian {
+                default_variance: 0.25,
+            },
+        ] {
+            let policy = ThompsonPolicy::new(posterior);
+            let mut manager = MCTSManager::new(
+                BanditGame(None),
+                ThompsonMCTS,
+                BanditEvaluator::default(),
+                policy,
+                (),
+            );
+            manager.seed(1);
+            manager.playout_n(5000).unwrap();
+            let visits: Vec<u64> = manager.tree().root_moves().map(|x| x.visits()).collect();
+            assert!(visits[1] > 9 * visits[0], "{:?}: {:?}", posterior, visits);
+        }
+    }
+
+    // Plays out the rest of the game with `Two` and reports the moves.
+    struct RolloutEvaluator;
+
+    #[derive(Debug)]
+    struct RaveMCTS;
+
+    impl MCTS for RaveMCTS {
+        type State = AddGame;
+        type Eval = RolloutEvaluator;
+        type TreePolicy = RAVEPolicy;
+        type NodeData = RaveData;
+        type TranspositionTable = ();
+        type ExtraThreadData = ();
+    }
+
+    impl Evaluator<RaveMCTS> for RolloutEvaluator {
+        type StateEvaluation = i64;
+
+        fn evaluate_new_state(
+            &self,
+            state: &AddGame,
+            moves: &Vec<Add>,
+            handle: Option<SearchHandle<RaveMCTS>>,
+        ) -> (Vec<()>, i64) {
+            let mut state = state.clone();
+            if let Some(mut handle) = handle {
+                while !state.available_moves().is_empty() {
+                    handle
+                        .thread_data()
+                        .policy_data
+                        .add_rollout_move(Add::Two, ());
+                    state.make_move(&Add::Two);
+                }
+            }
+            (vec![(); moves.len()], state.0)
+        }
+
+        fn interpret_evaluation_for_player(&self, evaln: &i64, _: &()) -> i64 {
+            *evaln
+        }
+
+        fn evaluate_existing_state(
+            &self,
+            _: &AddGame,
+            evaln: &i64,
+            _: SearchHandle<RaveMCTS>,
+        ) -> i64 {
+            *evaln
+        }
+    }
+
+    #[test]
+    fn rave_counts_rollout_moves() {
+        let mut chose_one = false;
+        for seed in 0..10 {
+            let policy = RAVEPolicy::new(1.0, RaveSchedule::Equivalence(100.0));
+            let mut manager = MCTSManager::new(AddGame(0), RaveMCTS, RolloutEvaluator, policy, ());
+            manager.seed(seed);
+            manager.playout_n(1).unwrap();
+
+            let node = manager.tree().root_node();
+            let amaf = node.data().stats(&node);
+            let one = node
+                .moves()
+                .position(|x| *x.get_move() == Add::One)
+                .unwrap();
+            let two = node
+                .moves()
+                .position(|x| *x.get_move() == Add::Two)
+                .unwrap();
+            chose_one |= node.moves().nth(one).unwrap().visits() == 1;
+            // `Two` was either chosen in the tree or played in the rollout
+            assert_eq!(amaf[two].get().0, 1);
+            assert_eq!(amaf[one].get().0, node.moves().nth(one).unwrap().visits());
+        }
+        assert!(chose_one);
+    }
+}